@@ -18,6 +18,8 @@ use tokio_tungstenite::{
 use tracing::{debug, error, info, trace, warn};
 
 pub mod messages;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
 pub mod requests;
 
 pub use messages::Message;
@@ -26,14 +28,15 @@ pub use requests::Request;
 pub type Writer = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, TMessage>;
 pub type Reader = SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>;
 
-const BELABOX_WS: &str = "wss://remote.belabox.net/ws/remote";
+/// The websocket of the public BELABOX Cloud.
+pub const BELABOX_WS: &str = "wss://remote.belabox.net/ws/remote";
 
 #[derive(Error, Debug)]
 pub enum BelaboxError {
     #[error("websocket error")]
-    Connect(#[source] tungstenite::Error),
+    Connect(#[source] Box<tungstenite::Error>),
     #[error("websocket send error")]
-    Send(#[source] tungstenite::Error),
+    Send(#[source] Box<tungstenite::Error>),
     #[error("disconnected from BELABOX Cloud")]
     Disconnected,
     #[error("auth failed")]
@@ -42,32 +45,57 @@ pub enum BelaboxError {
     ReceiverClosed(#[from] tokio::sync::oneshot::error::RecvError),
     #[error("Already restarting")]
     AlreadyRestarting,
+    #[error("invalid BELABOX Cloud URL {0}, it has to start with wss:// or ws://")]
+    InvalidUrl(String),
 }
 
 pub struct Belabox {
     pub run_handle: JoinHandle<()>,
     pub message_tx: Weak<broadcast::Sender<Message>>,
+    /// Every text message as received, before parsing
+    pub raw_tx: Weak<broadcast::Sender<String>>,
+    /// Log requests instead of sending them to the BELABOX
+    pub dry_run: bool,
     write: mpsc::UnboundedSender<InnerMessage>,
 }
 
 #[derive(Debug)]
 struct InnerMessage {
     pub respond: oneshot::Sender<Result<(), BelaboxError>>,
-    pub message: String,
+    pub message: TMessage,
 }
 
 impl Belabox {
-    pub async fn connect(key: String) -> Result<Self, BelaboxError> {
+    /// Connects to the BELABOX Cloud websocket at `url`, see [`BELABOX_WS`].
+    pub async fn connect(url: String, key: String) -> Result<Self, BelaboxError> {
+        let valid = reqwest::Url::parse(&url)
+            .map(|u| matches!(u.scheme(), "wss" | "ws") && u.has_host())
+            .unwrap_or(false);
+
+        if !valid {
+            return Err(BelaboxError::InvalidUrl(url));
+        }
+
         let (inner_tx, inner_rx) = mpsc::unbounded_channel();
         let (message_tx, _) = broadcast::channel(100);
         let message_tx = Arc::new(message_tx);
+        let (raw_tx, _) = broadcast::channel(100);
+        let raw_tx = Arc::new(raw_tx);
 
         let auth = requests::Remote::AuthKey { key, version: 6 };
-        let run_handle = tokio::spawn(run_loop(auth, message_tx.clone(), inner_rx));
+        let run_handle = tokio::spawn(run_loop(
+            url,
+            auth,
+            message_tx.clone(),
+            raw_tx.clone(),
+            inner_rx,
+        ));
 
         Ok(Self {
             run_handle,
             message_tx: Arc::downgrade(&message_tx),
+            raw_tx: Arc::downgrade(&raw_tx),
+            dry_run: false,
             write: inner_tx,
         })
     }
@@ -81,12 +109,30 @@ impl Belabox {
         Ok(tx.subscribe())
     }
 
+    /// Every text message from the BELABOX, including the ones belabot can't
+    /// parse.
+    pub fn raw_stream(&self) -> Result<broadcast::Receiver<String>, BelaboxError> {
+        let tx = self.raw_tx.upgrade().ok_or(BelaboxError::Disconnected)?;
+
+        Ok(tx.subscribe())
+    }
+
     pub async fn send(&self, request: Request) -> Result<(), BelaboxError> {
         let message = serde_json::to_string(&request).unwrap();
+
+        self.send_raw(message).await
+    }
+
+    /// Sends a message as is, the caller has to make sure it's valid.
+    pub async fn send_raw(&self, message: String) -> Result<(), BelaboxError> {
+        if self.dry_run {
+            info!(message, "Dry run, not sending request");
+            return Ok(());
+        }
         let (tx, rx) = oneshot::channel();
         let inner = InnerMessage {
             respond: tx,
-            message,
+            message: TMessage::Text(message),
         };
 
         self.write.send(inner).unwrap();
@@ -94,6 +140,26 @@ impl Belabox {
         rx.await.map_err(BelaboxError::ReceiverClosed)?
     }
 
+    /// Closes the connection to BELABOX Cloud without reconnecting.
+    pub async fn close(&self) {
+        // Stop reconnecting first, the close frame ends the connection
+        self.run_handle.abort();
+
+        let (tx, rx) = oneshot::channel();
+        let inner = InnerMessage {
+            respond: tx,
+            message: TMessage::Close(None),
+        };
+
+        if self.write.send(inner).is_err() {
+            return;
+        }
+
+        if let Ok(Err(e)) = rx.await {
+            debug!(?e, "failed to close the connection");
+        }
+    }
+
     pub async fn start(&self, start: requests::Start) -> Result<(), BelaboxError> {
         let request = Request::Start(start);
 
@@ -134,8 +200,10 @@ impl Belabox {
 }
 
 async fn run_loop(
+    url: String,
     auth: requests::Remote,
     message_tx: Arc<broadcast::Sender<Message>>,
+    raw_tx: Arc<broadcast::Sender<String>>,
     inner_rx: mpsc::UnboundedReceiver<InnerMessage>,
 ) {
     // Spawn thread to handle inner requests
@@ -143,7 +211,7 @@ async fn run_loop(
     tokio::spawn(handle_requests(inner_rx, request_write.clone()));
 
     loop {
-        let ws_stream = get_connection().await;
+        let ws_stream = get_connection(&url).await;
         let (mut write, read) = ws_stream.split();
 
         // Authenticate
@@ -161,27 +229,32 @@ async fn run_loop(
         let (cancel_tx, cancel_rx) = tokio::sync::oneshot::channel();
         tokio::spawn(keepalive(request_write.clone(), cancel_rx));
 
+        let _ = message_tx.send(Message::Connected(true));
+
         // Handle messages
-        if let Err(BelaboxError::AuthFailed) = handle_messages(read, message_tx.clone()).await {
-            break;
-        };
+        let result = handle_messages(read, message_tx.clone(), raw_tx.clone()).await;
 
         // Disconnected
         let _ = cancel_tx.send(());
+        let _ = message_tx.send(Message::Connected(false));
 
         {
             *request_write.lock().await = None;
         }
+
+        if let Err(BelaboxError::AuthFailed) = result {
+            break;
+        };
     }
 }
 
-async fn get_connection() -> WebSocketStream<MaybeTlsStream<TcpStream>> {
+async fn get_connection(url: &str) -> WebSocketStream<MaybeTlsStream<TcpStream>> {
     let mut retry_grow = 1;
 
     loop {
         info!("Connecting");
 
-        if let Ok((ws_stream, _)) = tokio_tungstenite::connect_async(BELABOX_WS).await {
+        if let Ok((ws_stream, _)) = tokio_tungstenite::connect_async(url).await {
             info!("Connected");
             break ws_stream;
         }
@@ -227,6 +300,7 @@ async fn keepalive(write: Arc<Mutex<Option<Writer>>>, mut cancel_rx: oneshot::Re
 async fn handle_messages(
     mut read: Reader,
     message_tx: Arc<broadcast::Sender<Message>>,
+    raw_tx: Arc<broadcast::Sender<String>>,
 ) -> Result<(), BelaboxError> {
     while let Some(Ok(message)) = read.next().await {
         if let TMessage::Close(info) = &message {
@@ -238,6 +312,8 @@ async fn handle_messages(
         }
 
         if let TMessage::Text(text) = &message {
+            let _ = raw_tx.send(text.to_owned());
+
             let text: serde_json::Value = match serde_json::from_str(text) {
                 Ok(o) => o,
                 Err(e) => {
@@ -292,9 +368,9 @@ async fn handle_requests(
         let mut lock = write.lock().await;
         if let Some(w) = lock.as_mut() {
             let res = w
-                .send(TMessage::Text(request.message))
+                .send(request.message)
                 .await
-                .map_err(BelaboxError::Send);
+                .map_err(|e| BelaboxError::Send(Box::new(e)));
 
             request.respond.send(res).unwrap();
         } else {
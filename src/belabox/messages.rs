@@ -1,8 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "camelCase", untagged)]
 pub enum Message {
     Config(Config),
@@ -16,10 +16,19 @@ pub enum Message {
     Wifi(WifiChange),
     StreamingStatus(StreamingStatus),
     Notification(Notification),
+    NotificationRemove(NotificationRemove),
     Bitrate(Bitrate),
     Pipelines(HashMap<String, Pipeline>),
     Acodecs(HashMap<String, String>),
     Asrcs(Asrcs),
+    Relays(Relays),
+    /// Sent by belabot when the connection to BELABOX Cloud is established,
+    /// `false` when it was lost
+    #[serde(skip)]
+    Connected(bool),
+    /// Keep this last, every field except the status is optional so it
+    /// would match other messages as well
+    Modems(HashMap<String, Modem>),
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -49,6 +58,10 @@ pub struct Config {
     pub ssh_pass: Option<String>,
     pub asrc: String,
     pub acodec: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relay_server: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relay_account: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -66,6 +79,82 @@ pub struct Pipeline {
     pub name: String,
 }
 
+/// The BELABOX Cloud relay servers and accounts.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Relays {
+    pub servers: HashMap<String, RelayServer>,
+    #[serde(default)]
+    pub accounts: HashMap<String, RelayAccount>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RelayServer {
+    pub name: String,
+    #[serde(default)]
+    pub default: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RelayAccount {
+    pub name: String,
+}
+
+/// A cellular modem, updates only contain the fields which changed.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Modem {
+    pub ifname: Option<String>,
+    pub name: Option<String>,
+    pub status: ModemStatus,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct ModemStatus {
+    pub connection: Option<String>,
+    /// The carrier
+    pub network: Option<String>,
+    /// For example `LTE` or `5G NSA`
+    pub network_type: Option<String>,
+    /// Signal quality in percent
+    pub signal: Option<u32>,
+    pub roaming: Option<bool>,
+}
+
+impl Modem {
+    /// Applies an update of the modem.
+    pub fn merge(&mut self, update: Modem) {
+        if update.ifname.is_some() {
+            self.ifname = update.ifname;
+        }
+
+        if update.name.is_some() {
+            self.name = update.name;
+        }
+
+        let status = &mut self.status;
+        let update = update.status;
+
+        if update.connection.is_some() {
+            status.connection = update.connection;
+        }
+
+        if update.network.is_some() {
+            status.network = update.network;
+        }
+
+        if update.network_type.is_some() {
+            status.network_type = update.network_type;
+        }
+
+        if update.signal.is_some() {
+            status.signal = update.signal;
+        }
+
+        if update.roaming.is_some() {
+            status.roaming = update.roaming;
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct StreamingStatus {
     pub is_streaming: bool,
@@ -136,6 +225,19 @@ pub struct Sensors {
     pub soc_current: Option<String>,
     #[serde(rename = "SoC temperature")]
     pub soc_temperature: String,
+    /// Every other sensor by its label, for example `Case temperature`
+    #[serde(flatten)]
+    pub other: BTreeMap<String, String>,
+}
+
+impl Sensors {
+    /// The SoC temperature in celsius, the BELABOX sends it as `45.0 °C`.
+    pub fn soc_temperature_celsius(&self) -> Option<f64> {
+        self.soc_temperature
+            .split_whitespace()
+            .next()
+            .and_then(|t| t.parse().ok())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -164,6 +266,12 @@ pub struct NotificationMessage {
     pub kind: String,
 }
 
+/// The notifications with these names are resolved.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct NotificationRemove {
+    pub remove: Vec<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct Bitrate {
     pub max_br: u32,
@@ -208,6 +316,89 @@ mod tests {
         assert_eq!(parsed, expected);
     }
 
+    #[test]
+    fn soc_temperature() {
+        let message = r#"{"sensors":{"SoC temperature":"45.5 °C"}}"#;
+
+        let parsed = deserialize(message);
+
+        match parsed {
+            Message::Sensors(sensors) => {
+                assert_eq!(sensors.soc_temperature_celsius(), Some(45.5));
+            }
+            m => panic!("unexpected message {:?}", m),
+        }
+    }
+
+    #[test]
+    fn other_sensors() {
+        let message = r#"{"sensors":{"SoC temperature":"45.5 °C","Case temperature":"38.0 °C","Modem temperature":"52.0 °C"}}"#;
+
+        let parsed = deserialize(message);
+
+        match parsed {
+            Message::Sensors(sensors) => {
+                assert_eq!(sensors.other.len(), 2);
+                assert_eq!(sensors.other["Case temperature"], "38.0 °C");
+            }
+            m => panic!("unexpected message {:?}", m),
+        }
+    }
+
+    #[test]
+    fn relays() {
+        let message = r#"{"relays":{"servers":{"eu":{"name":"Europe","default":true},"na":{"name":"North America"}},"accounts":{}}}"#;
+
+        let parsed = deserialize(message);
+
+        match parsed {
+            Message::Relays(relays) => {
+                assert_eq!(relays.servers.len(), 2);
+                assert!(relays.servers["eu"].default);
+                assert_eq!(relays.servers["na"].name, "North America");
+            }
+            m => panic!("unexpected message {:?}", m),
+        }
+    }
+
+    #[test]
+    fn notification_remove() {
+        let message = r#"{"notification":{"remove":["modem_usb0"]}}"#;
+
+        let parsed = deserialize(message);
+
+        let expected = Message::NotificationRemove(NotificationRemove {
+            remove: vec!["modem_usb0".to_string()],
+        });
+
+        assert_eq!(parsed, expected);
+    }
+
+    #[test]
+    fn modems() {
+        let message = r#"{"modems":{"0":{"ifname":"usb0","name":"Quectel","status":{"connection":"connected","network":"T-Mobile","network_type":"5G NSA","signal":78,"roaming":false}}}}"#;
+
+        let parsed = deserialize(message);
+
+        let mut modems = match parsed {
+            Message::Modems(m) => m,
+            m => panic!("unexpected message {:?}", m),
+        };
+
+        let update = r#"{"modems":{"0":{"status":{"signal":50}}}}"#;
+        let update = match deserialize(update) {
+            Message::Modems(mut m) => m.remove("0").unwrap(),
+            m => panic!("unexpected message {:?}", m),
+        };
+
+        let modem = modems.get_mut("0").unwrap();
+        modem.merge(update);
+
+        assert_eq!(modem.ifname.as_deref(), Some("usb0"));
+        assert_eq!(modem.status.network_type.as_deref(), Some("5G NSA"));
+        assert_eq!(modem.status.signal, Some(50));
+    }
+
     fn deserialize(json: &str) -> Message {
         let text: serde_json::Value = serde_json::from_str(json).unwrap();
         let text = text.as_object().unwrap();
@@ -0,0 +1,170 @@
+//! A local BELABOX Cloud for tests. It accepts any key, replays canned
+//! messages and records the requests, so commands can be tested without an
+//! encoder.
+
+use std::io;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::{broadcast, mpsc, Mutex},
+    task::JoinHandle,
+    time::{self, Duration},
+};
+use tokio_tungstenite::tungstenite::Message as TMessage;
+use tracing::debug;
+
+use super::{Belabox, Message};
+
+/// How long [`MockBelabox::request`] waits for the client.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Connections are accepted after this, a client subscribes to the messages
+/// after connecting and would miss the first ones of an instant server.
+const ACCEPT_DELAY: Duration = Duration::from_millis(50);
+
+pub struct MockBelabox {
+    /// The websocket URL to connect [`Belabox`] to
+    pub url: String,
+    messages: broadcast::Sender<String>,
+    requests: Mutex<mpsc::UnboundedReceiver<serde_json::Value>>,
+    handle: JoinHandle<()>,
+}
+
+impl MockBelabox {
+    /// Listens on a free local port, the `script` messages are sent after
+    /// every login.
+    pub async fn start(script: Vec<String>) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("ws://{}", listener.local_addr()?);
+
+        let (messages, _) = broadcast::channel(100);
+        let (requests_tx, requests) = mpsc::unbounded_channel();
+
+        let handle = {
+            let messages = messages.clone();
+
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(serve(
+                        stream,
+                        script.to_owned(),
+                        messages.subscribe(),
+                        requests_tx.clone(),
+                    ));
+                }
+            })
+        };
+
+        Ok(Self {
+            url,
+            messages,
+            requests: Mutex::new(requests),
+            handle,
+        })
+    }
+
+    /// Connects a client and waits until it's logged in.
+    pub async fn connect(&self) -> Belabox {
+        let belabox = Belabox::connect(self.url.to_owned(), "key".to_owned())
+            .await
+            .expect("the mock URL is valid");
+        let mut messages = belabox.message_stream().expect("the client is running");
+
+        while let Ok(message) = messages.recv().await {
+            if let Message::RemoteAuth(_) = message {
+                break;
+            }
+        }
+
+        belabox
+    }
+
+    /// Sends a message to the connected clients, as if the BELABOX sent it.
+    pub fn send(&self, message: &str) {
+        let _ = self.messages.send(message.to_owned());
+    }
+
+    /// The next request of a client, keepalives are left out. `None` when
+    /// nothing was sent within a second.
+    pub async fn request(&self) -> Option<serde_json::Value> {
+        let mut requests = self.requests.lock().await;
+
+        time::timeout(REQUEST_TIMEOUT, requests.recv())
+            .await
+            .ok()
+            .flatten()
+    }
+}
+
+impl Drop for MockBelabox {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn serve(
+    stream: TcpStream,
+    script: Vec<String>,
+    mut messages: broadcast::Receiver<String>,
+    requests: mpsc::UnboundedSender<serde_json::Value>,
+) {
+    time::sleep(ACCEPT_DELAY).await;
+
+    let ws_stream = match tokio_tungstenite::accept_async(stream).await {
+        Ok(s) => s,
+        Err(e) => {
+            debug!(?e, "mock handshake failed");
+            return;
+        }
+    };
+    let (mut write, mut read) = ws_stream.split();
+
+    loop {
+        tokio::select! {
+            message = read.next() => {
+                let text = match message {
+                    Some(Ok(TMessage::Text(t))) => t,
+                    Some(Ok(_)) => continue,
+                    _ => break,
+                };
+
+                let request: serde_json::Value = match serde_json::from_str(&text) {
+                    Ok(r) => r,
+                    Err(_) => continue,
+                };
+
+                if request.get("keepalive").is_some() {
+                    continue;
+                }
+
+                // Any key is accepted
+                if request.get("remote").is_some() {
+                    let mut replies = vec![r#"{"remote":{"auth/key":true}}"#.to_owned()];
+                    replies.extend(script.iter().cloned());
+
+                    for reply in replies {
+                        if write.send(TMessage::Text(reply)).await.is_err() {
+                            return;
+                        }
+                    }
+
+                    continue;
+                }
+
+                let _ = requests.send(request);
+            }
+            message = messages.recv() => {
+                let message = match message {
+                    Ok(m) => m,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+
+                if write.send(TMessage::Text(message)).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
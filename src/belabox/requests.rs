@@ -40,6 +40,10 @@ pub struct Start {
     pub bitrate_overlay: bool,
     pub asrc: String,
     pub acodec: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relay_server: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relay_account: Option<String>,
 }
 
 impl From<super::messages::Config> for Start {
@@ -55,6 +59,8 @@ impl From<super::messages::Config> for Start {
             bitrate_overlay: c.bitrate_overlay,
             asrc: c.asrc,
             acodec: c.acodec,
+            relay_server: c.relay_server,
+            relay_account: c.relay_account,
         }
     }
 }
@@ -64,7 +70,7 @@ pub struct Bitrate {
     pub max_br: u32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Netif {
     pub name: String,
     pub ip: String,
@@ -99,6 +105,8 @@ mod tests {
             bitrate_overlay: false,
             asrc: "No audio".to_string(),
             acodec: "opus".to_string(),
+            relay_server: None,
+            relay_account: None,
         });
 
         let json = serde_json::to_string(&message).unwrap();
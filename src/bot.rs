@@ -1,16 +1,30 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+    sync::Arc,
+};
 
 use tokio::{
-    sync::{broadcast::Receiver, RwLock},
+    sync::{broadcast::Receiver, watch, Mutex, RwLock},
     task::JoinHandle,
     time,
 };
+use tracing::{error, info, warn};
 
 use crate::{
     belabox,
-    config::{self, BotCommand},
+    command_handler::{Cooldowns, Device},
+    config,
+    discord::Discord,
     error::Error,
+    metrics,
+    monitor::Throttle,
+    obs::Obs,
+    status,
+    templates::Templates,
     twitch::HandleMessage,
+    update::UpdateCheck,
+    usage::{self, Usage},
     Belabox, CommandHandler, Monitor, Settings, Twitch,
 };
 
@@ -18,8 +32,15 @@ pub struct Bot {
     pub bb_msg_handle: JoinHandle<()>,
     pub bb_monitor_handle: JoinHandle<()>,
     pub tw_msg_handle: JoinHandle<()>,
+    /// Handles of the additional devices and background tasks
+    pub device_handles: Vec<JoinHandle<()>>,
     pub twitch: Arc<Twitch>,
+    /// The primary device
     pub belabox: Arc<Belabox>,
+    /// Every device, the primary first
+    pub belaboxes: Vec<Arc<Belabox>>,
+    usage: Arc<Mutex<Usage>>,
+    usage_file: Option<PathBuf>,
 }
 
 #[derive(Debug, Default)]
@@ -27,58 +48,322 @@ pub struct BelaState {
     pub online: bool,
     pub is_streaming: bool,
     pub restart: bool,
+    /// When `Restart` stopped the stream, until it streams again or times out
+    pub resuming: Option<time::Instant>,
     pub notify_ups: Option<bool>,
+    pub notify_streaming: Option<bool>,
+    pub notify_bitrate_low: bool,
+    /// The latest total bitrates in kbps, the oldest first
+    pub bitrate_history: VecDeque<u64>,
+    pub bitrate_low_since: Option<time::Instant>,
+    /// Since when the bitrate is 0 kbps while streaming
+    pub stalled_since: Option<time::Instant>,
+    /// Automatic restarts because of a stall and when the last one was
+    pub stall_restarts: u32,
+    pub stall_restarted: Option<time::Instant>,
+    /// The automatic restarts reached the limit
+    pub stall_gave_up: bool,
+    pub temperature_warned: Option<time::Instant>,
+    /// Destructive commands waiting to be confirmed
+    pub pending_confirmations: HashMap<config::BotCommand, time::Instant>,
+    /// The current stream, `None` when not streaming
+    pub session: Option<Session>,
     pub config: Option<belabox::messages::Config>,
     pub netif: Option<HashMap<String, belabox::messages::Netif>>,
     pub sensors: Option<belabox::messages::Sensors>,
+    pub modems: Option<HashMap<String, belabox::messages::Modem>>,
     pub notification_timeout: HashMap<String, time::Instant>,
+    /// Notifications sent to chat which haven't been resolved, by name
+    pub active_notifications: HashMap<String, String>,
     pub pipelines: Option<HashMap<String, belabox::messages::Pipeline>>,
     pub asrcs: Option<Vec<String>>,
+    pub relays: Option<belabox::messages::Relays>,
+    /// Whether the websocket to BELABOX Cloud is connected
+    pub connected: bool,
+    /// How many times the websocket to BELABOX Cloud connected
+    pub connections: u64,
+    /// When the last message from the BELABOX was received
+    pub last_message: Option<time::Instant>,
+    pub notify_connected: Option<bool>,
+    pub notify_encoder_online: Option<bool>,
+}
+
+/// Statistics of the current stream.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub started: time::Instant,
+    bitrate_sum: u64,
+    bitrate_samples: u64,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self {
+            started: time::Instant::now(),
+            bitrate_sum: 0,
+            bitrate_samples: 0,
+        }
+    }
+
+    /// Adds a total bitrate in kbps to the average.
+    pub fn add_bitrate(&mut self, kbps: u64) {
+        self.bitrate_sum += kbps;
+        self.bitrate_samples += 1;
+    }
+
+    /// The average total bitrate in kbps since the stream started.
+    pub fn average_bitrate(&self) -> Option<u64> {
+        self.bitrate_sum.checked_div(self.bitrate_samples)
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Bot {
     pub async fn new(config: Settings) -> Result<Self, Error> {
-        let twitch = Arc::new(Twitch::run(config.twitch.clone()).await?);
-        let belabox = Arc::new(Belabox::connect(config.belabox.remote_key.to_owned()).await?);
-
-        // Create state to store BELABOX information
-        let bela_state = Arc::new(RwLock::new(BelaState::default()));
-
-        // Read BELABOX messages
-        let bb_msg_handle = tokio::spawn(handle_belabox_messages(
-            belabox.message_stream()?,
-            belabox.clone(),
-            twitch.clone(),
-            bela_state.clone(),
-        ));
+        let twitch = Twitch::run(config.twitch.clone(), config.path.clone()).await?;
 
-        let bb_monitor_handle = tokio::spawn(handle_belabox_monitor(
-            belabox.message_stream()?,
-            belabox.clone(),
-            twitch.clone(),
-            config.belabox.monitor,
-            bela_state.clone(),
-        ));
+        Self::with_twitch(config, twitch).await
+    }
+
+    /// Starts the bot on a chat connection which is already set up, such as
+    /// an offline one.
+    pub async fn with_twitch(config: Settings, twitch: Twitch) -> Result<Self, Error> {
+        let twitch = Arc::new(twitch);
+        let discord = config
+            .notifications
+            .discord_webhook_url
+            .to_owned()
+            .map(Discord::new);
+        let templates = Arc::new(Templates::new(config.messages));
+        let obs = config.obs.enabled.then(|| Obs::new(config.obs));
+        let (monitor_tx, monitor_rx) = watch::channel(config.belabox.monitor);
+
+        let shared = Shared {
+            twitch: twitch.clone(),
+            discord,
+            obs,
+            templates: templates.clone(),
+            monitor: monitor_rx,
+            targets: config.notifications.targets,
+            dry_run: config.dry_run,
+        };
+
+        let primary = config::Device {
+            remote_key: config.belabox.remote_key.to_owned(),
+            cloud_url: config.belabox.cloud_url.to_owned(),
+            custom_interface_name: config.belabox.custom_interface_name,
+            outputs: config.belabox.outputs,
+        };
+
+        let (primary, bb_msg_handle, bb_monitor_handle) =
+            spawn_device(None, primary, shared.clone()).await?;
+        let belabox = primary.belabox.clone();
+
+        let mut devices = vec![primary];
+        let mut device_handles = Vec::new();
+
+        for (name, mut device) in config.belabox.devices {
+            if device.cloud_url.is_none() {
+                device.cloud_url = config.belabox.cloud_url.to_owned();
+            }
+
+            // Only the primary device switches OBS scenes
+            let shared = Shared {
+                obs: None,
+                ..shared.clone()
+            };
+
+            let (device, msg_handle, monitor_handle) =
+                spawn_device(Some(name), device, shared).await?;
+
+            devices.push(device);
+            device_handles.push(msg_handle);
+            device_handles.push(monitor_handle);
+        }
+
+        if config.status_server.enabled {
+            status::spawn(config.status_server.address, devices.clone()).await?;
+        }
+
+        let usage = match &config.usage.file {
+            Some(path) => Usage::load(path),
+            None => Usage::default(),
+        };
+        let usage = Arc::new(Mutex::new(usage));
+
+        if let Some(path) = &config.usage.file {
+            let interval = time::Duration::from_secs(config.usage.save_interval_secs.max(1));
+            device_handles.push(tokio::spawn(usage::save_loop(
+                usage.clone(),
+                path.to_owned(),
+                interval,
+            )));
+        }
+
+        if config.metrics.enabled {
+            metrics::spawn(config.metrics.address, devices.clone(), usage.clone()).await?;
+        }
+
+        if let Some(secs) = config.logging.heartbeat_secs {
+            device_handles.push(tokio::spawn(heartbeat(
+                time::Duration::from_secs(secs.max(1)),
+                twitch.clone(),
+                devices.clone(),
+            )));
+        }
+
+        let belaboxes = devices.iter().map(|d| d.belabox.clone()).collect();
 
         // Read Twitch messages
+        let command_handler = CommandHandler {
+            twitch: twitch.clone(),
+            commands: config.commands,
+            admins: config.twitch.admins,
+            user_overrides: config.twitch.user_overrides,
+            groups: config.twitch.groups,
+            cooldowns: Mutex::new(match &config.safety.cooldown_file {
+                Some(path) => Cooldowns::load(path),
+                None => Cooldowns::default(),
+            }),
+            cooldown_file: config.safety.cooldown_file,
+            devices,
+            templates,
+            confirm_secs: config.safety.confirm_secs,
+            allow_raw: config.safety.allow_raw,
+            read_only: config.safety.read_only,
+            unknown_commands: config.unknown_commands,
+            check_updates: config.check_updates,
+            update_check: Mutex::new(UpdateCheck::default()),
+            started: time::Instant::now(),
+            monitor: monitor_tx,
+            path: config.path.to_owned(),
+            usage: usage.clone(),
+        };
         let tw_msg_handle = tokio::spawn(handle_twitch_messages(
             twitch.message_stream()?,
-            belabox.clone(),
-            twitch.clone(),
-            config.commands,
-            config.belabox.custom_interface_name,
-            config.twitch.admins,
-            bela_state,
+            command_handler,
         ));
 
         Ok(Self {
             bb_msg_handle,
             bb_monitor_handle,
             tw_msg_handle,
+            device_handles,
             twitch,
             belabox,
+            belaboxes,
+            usage,
+            usage_file: config.usage.file,
         })
     }
+
+    /// Stops handling commands, saves the usage stats, closes the BELABOX
+    /// connections and leaves the Twitch channels.
+    pub async fn shutdown(self) {
+        info!("Shutting down");
+
+        self.tw_msg_handle.abort();
+
+        if let Some(path) = &self.usage_file {
+            if let Err(e) = self.usage.lock().await.save(path) {
+                error!(?e, "failed to save the usage stats");
+            }
+        }
+
+        for belabox in &self.belaboxes {
+            belabox.close().await;
+        }
+
+        self.twitch.close().await;
+    }
+}
+
+/// Used by every device.
+#[derive(Clone)]
+struct Shared {
+    twitch: Arc<Twitch>,
+    discord: Option<Discord>,
+    obs: Option<Obs>,
+    templates: Arc<Templates>,
+    /// The monitor settings, they change when the config is reloaded
+    monitor: watch::Receiver<config::Monitor>,
+    targets: config::NotificationTargets,
+    dry_run: bool,
+}
+
+/// Connects to a BELABOX and starts reading and monitoring its messages.
+async fn spawn_device(
+    name: Option<String>,
+    device: config::Device,
+    shared: Shared,
+) -> Result<(Device, JoinHandle<()>, JoinHandle<()>), Error> {
+    let Shared {
+        twitch,
+        discord,
+        obs,
+        templates,
+        monitor,
+        targets,
+        dry_run,
+    } = shared;
+
+    let url = device
+        .cloud_url
+        .unwrap_or_else(|| belabox::BELABOX_WS.to_string());
+    let mut belabox = Belabox::connect(url, device.remote_key).await?;
+    belabox.dry_run = dry_run;
+    let belabox = Arc::new(belabox);
+
+    // Create state to store BELABOX information
+    let bela_state = Arc::new(RwLock::new(BelaState::default()));
+
+    let prefix = match &name {
+        Some(name) => format!("BB {}", name),
+        None => "BB".to_string(),
+    };
+
+    // Read BELABOX messages
+    let msg_handle = tokio::spawn(handle_belabox_messages(
+        belabox.message_stream()?,
+        belabox.clone(),
+        twitch.clone(),
+        bela_state.clone(),
+        prefix.to_owned(),
+        templates.clone(),
+        device.custom_interface_name.clone(),
+    ));
+
+    let handler = Monitor {
+        prefix,
+        belabox: belabox.clone(),
+        bela_state: bela_state.clone(),
+        twitch: twitch.clone(),
+        discord,
+        targets,
+        obs,
+        templates: templates.clone(),
+        throttle: Throttle::default(),
+        settings: monitor.clone(),
+    };
+    let monitor_handle = tokio::spawn(handle_belabox_monitor(belabox.message_stream()?, handler));
+
+    let device = Device {
+        name,
+        twitch,
+        belabox,
+        bela_state,
+        custom_interface_name: Arc::new(std::sync::RwLock::new(device.custom_interface_name)),
+        outputs: device.outputs,
+        templates,
+        monitor,
+    };
+
+    Ok((device, msg_handle, monitor_handle))
 }
 
 async fn handle_belabox_messages(
@@ -86,10 +371,26 @@ async fn handle_belabox_messages(
     belabox: Arc<Belabox>,
     twitch: Arc<Twitch>,
     bela_state: Arc<RwLock<BelaState>>,
+    prefix: String,
+    templates: Arc<Templates>,
+    custom_interface_name: HashMap<String, String>,
 ) {
     use belabox::Message;
 
     while let Ok(message) = bb_msg.recv().await {
+        if let Message::Connected(connected) = message {
+            let mut lock = bela_state.write().await;
+            lock.connected = connected;
+            lock.last_message = connected.then(time::Instant::now);
+
+            if connected {
+                lock.connections += 1;
+            }
+            continue;
+        }
+
+        bela_state.write().await.last_message = Some(time::Instant::now());
+
         match message {
             Message::Config(config) => {
                 let mut lock = bela_state.write().await;
@@ -101,6 +402,11 @@ async fn handle_belabox_messages(
             }
             Message::Netif(netif) => {
                 let mut lock = bela_state.write().await;
+
+                if lock.netif.is_none() {
+                    warn_unknown_interfaces(&prefix, &custom_interface_name, &netif);
+                }
+
                 lock.netif = Some(netif);
             }
             Message::Sensors(sensors) => {
@@ -118,19 +424,37 @@ async fn handle_belabox_messages(
                 lock.is_streaming = status.is_streaming;
             }
             Message::Status(status) => {
-                let mut lock = bela_state.write().await;
-                lock.is_streaming = status.is_streaming;
-                lock.asrcs = Some(status.asrcs);
+                // The lock isn't held while starting and sending
+                let request = {
+                    let mut lock = bela_state.write().await;
+                    lock.is_streaming = status.is_streaming;
+                    lock.asrcs = Some(status.asrcs);
+
+                    if lock.restart {
+                        lock.restart = false;
+                        lock.config.to_owned().map(belabox::requests::Start::from)
+                    } else {
+                        None
+                    }
+                };
 
-                if lock.restart {
-                    lock.restart = false;
+                if let Some(request) = request {
+                    let _ = belabox.start(request).await;
 
-                    if let Some(config) = &lock.config {
-                        let request = belabox::requests::Start::from(config.to_owned());
-                        let _ = belabox.start(request).await;
+                    let msg = format!("{}: {}", prefix, templates.get("restart_done"));
+                    let _ = twitch.send(msg).await;
+                }
+            }
+            Message::Modems(modems) => {
+                let mut lock = bela_state.write().await;
+                let current = lock.modems.get_or_insert_with(HashMap::new);
 
-                        let msg = "BB: Reboot successful, starting the stream".to_string();
-                        let _ = twitch.send(msg).await;
+                for (id, modem) in modems {
+                    match current.get_mut(&id) {
+                        Some(c) => c.merge(modem),
+                        None => {
+                            current.insert(id, modem);
+                        }
                     }
                 }
             }
@@ -138,6 +462,10 @@ async fn handle_belabox_messages(
                 let mut lock = bela_state.write().await;
                 lock.pipelines = Some(pipelines);
             }
+            Message::Relays(relays) => {
+                let mut lock = bela_state.write().await;
+                lock.relays = Some(relays);
+            }
             Message::Asrcs(status) => {
                 let mut lock = bela_state.write().await;
                 lock.asrcs = Some(status.asrcs);
@@ -147,37 +475,58 @@ async fn handle_belabox_messages(
     }
 }
 
-async fn handle_belabox_monitor(
-    bb_msg: Receiver<belabox::Message>,
-    belabox: Arc<Belabox>,
-    twitch: Arc<Twitch>,
-    monitor: config::Monitor,
-    bela_state: Arc<RwLock<BelaState>>,
+/// Warns about custom interface names of interfaces the BELABOX doesn't have.
+fn warn_unknown_interfaces(
+    prefix: &str,
+    custom_interface_name: &HashMap<String, String>,
+    netif: &HashMap<String, belabox::messages::Netif>,
 ) {
-    let handler = Monitor {
-        belabox,
-        bela_state,
-        twitch,
-    };
-    handler.run(bb_msg, monitor).await;
+    for (interface, name) in custom_interface_name {
+        let known = netif.contains_key(interface) || netif.values().any(|n| &n.ip == interface);
+
+        if !known {
+            warn!(
+                device = prefix,
+                interface,
+                name,
+                "The BELABOX doesn't report this interface, its custom name is unused"
+            );
+        }
+    }
 }
 
-async fn handle_twitch_messages(
-    tw_msg: Receiver<HandleMessage>,
-    belabox: Arc<Belabox>,
-    twitch: Arc<Twitch>,
-    commands: HashMap<BotCommand, config::CommandInformation>,
-    custom_interface_name: HashMap<String, String>,
-    admins: Vec<String>,
-    bela_state: Arc<RwLock<BelaState>>,
-) {
-    let handler = CommandHandler {
-        twitch,
-        belabox,
-        bela_state,
-        commands,
-        custom_interface_name,
-        admins,
-    };
+/// Logs the state of the connections every `interval`, so the logs show that
+/// the bot is still running during quiet streams.
+async fn heartbeat(interval: time::Duration, twitch: Arc<Twitch>, devices: Vec<Device>) {
+    let mut interval = time::interval(interval);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        let twitch_joined = twitch.is_joined().await;
+
+        for device in &devices {
+            let state = device.bela_state.read().await;
+            let last_message_secs = state.last_message.map(|t| t.elapsed().as_secs());
+
+            info!(
+                twitch = twitch_joined,
+                device = device.name.as_deref().unwrap_or("primary"),
+                belabox_cloud = state.connected,
+                encoder_online = state.online,
+                streaming = state.is_streaming,
+                last_message_secs,
+                "Heartbeat"
+            );
+        }
+    }
+}
+
+async fn handle_belabox_monitor(bb_msg: Receiver<belabox::Message>, handler: Monitor) {
+    handler.run(bb_msg).await;
+}
+
+async fn handle_twitch_messages(tw_msg: Receiver<HandleMessage>, mut handler: CommandHandler) {
     handler.run(tw_msg).await;
 }
@@ -1,87 +1,998 @@
-use std::fmt::Write as _;
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    iter::Peekable,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
 
-use tokio::sync::{broadcast, RwLock};
-use tracing::{debug, error, info};
+use thiserror::Error;
+use tokio::{
+    sync::{broadcast, watch, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+use tracing::{debug, error, info, warn};
 
 use crate::{
     belabox::{self, BelaboxError},
     bot::BelaState,
-    config::{self, BotCommand, Permission},
-    error::{Error, Result},
-    twitch, Belabox, Twitch,
+    config::{self, BotCommand, Permission, ReplyMode},
+    error::Error,
+    templates::Templates,
+    twitch,
+    update::{self, UpdateCheck},
+    usage::{self, Usage},
+    Belabox, Twitch,
 };
 
+/// How many commands and users are shown by `Top`.
+const TOP_AMOUNT: usize = 5;
+
+/// How long to wait for the BELABOX to confirm enabling or disabling an
+/// interface.
+const INTERFACE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for the response to a raw message.
+const RAW_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longer raw responses are cut off to keep chat readable.
+const RAW_RESPONSE_MAX_CHARS: usize = 400;
+
+/// How often a user is told they can't use a command.
+const PERMISSION_DENIED_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Why a command failed, each has its own chat message so the cause is clear
+/// during a stream.
+#[derive(Error, Debug)]
+pub enum CommandError {
+    #[error("not connected to BELABOX Cloud")]
+    Disconnected,
+    #[error("the BELABOX is offline")]
+    Offline,
+    #[error("no data from the BELABOX")]
+    Stale,
+    #[error("permission denied")]
+    PermissionDenied,
+    /// The message is shown with the usage of the command
+    #[error("{0}")]
+    InvalidArgument(String),
+    /// An error reported by the BELABOX, for example a full storage
+    #[error("{0}")]
+    Device(String),
+    #[error("command failed")]
+    Failed(#[source] Error),
+}
+
+impl CommandError {
+    /// The key of the chat message.
+    fn message_key(&self) -> &'static str {
+        match self {
+            CommandError::Disconnected => "disconnected",
+            CommandError::Offline => "offline",
+            CommandError::Stale => "stale",
+            CommandError::PermissionDenied => "permission_denied",
+            CommandError::InvalidArgument(_) => "usage",
+            CommandError::Device(_) => "device_error",
+            CommandError::Failed(_) => "error",
+        }
+    }
+}
+
+impl From<BelaboxError> for CommandError {
+    fn from(e: BelaboxError) -> Self {
+        match e {
+            BelaboxError::Disconnected => CommandError::Disconnected,
+            e => CommandError::Failed(Error::Belabox(e)),
+        }
+    }
+}
+
+pub type CommandResult = std::result::Result<String, CommandError>;
+
 pub struct CommandHandler {
     pub twitch: Arc<Twitch>,
-    pub belabox: Arc<Belabox>,
-    pub bela_state: Arc<RwLock<BelaState>>,
     pub commands: HashMap<config::BotCommand, config::CommandInformation>,
-    pub custom_interface_name: HashMap<String, String>,
     pub admins: Vec<String>,
+    pub user_overrides: HashMap<String, Permission>,
+    /// Users of the groups used by `Permission::Group`
+    pub groups: HashMap<String, Vec<String>>,
+    pub cooldowns: Mutex<Cooldowns>,
+    /// The cooldowns are saved to this file after every change
+    pub cooldown_file: Option<PathBuf>,
+    /// The BELABOX devices, the first one is the primary device
+    pub devices: Vec<Device>,
+    pub templates: Arc<Templates>,
+    /// Seconds to confirm destructive commands, 0 disables the confirmation
+    pub confirm_secs: u64,
+    /// Allows `Raw` to send any message to the BELABOX
+    pub allow_raw: bool,
+    /// Only commands which show the status can be used
+    pub read_only: bool,
+    pub unknown_commands: config::UnknownCommands,
+    /// `Version` checks GitHub for a newer release
+    pub check_updates: bool,
+    pub update_check: Mutex<UpdateCheck>,
+    pub usage: Arc<Mutex<Usage>>,
+    /// When the bot was started
+    pub started: Instant,
+    /// Sends the monitor settings to the devices after reloading the config
+    pub monitor: watch::Sender<config::Monitor>,
+    /// The config file, `Reload` loads it again
+    pub path: Option<PathBuf>,
+}
+
+/// A BELABOX which can be controlled with commands.
+#[derive(Clone)]
+pub struct Device {
+    /// Name used to target the device in commands, `None` for the primary device
+    pub name: Option<String>,
+    pub twitch: Arc<Twitch>,
+    pub belabox: Arc<Belabox>,
+    pub bela_state: Arc<RwLock<BelaState>>,
+    /// Changed by `Label` while the bot runs
+    pub custom_interface_name: Arc<std::sync::RwLock<HashMap<String, String>>>,
+    /// Where the stream can be switched to with `Output`
+    pub outputs: BTreeMap<String, config::Output>,
+    pub templates: Arc<Templates>,
+    /// The monitor settings, `stale_secs` is how long the BELABOX can be
+    /// silent before it counts as offline
+    pub monitor: watch::Receiver<config::Monitor>,
+}
+
+/// The cooldowns as saved to the file, in seconds since the unix epoch.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+struct SavedCooldowns {
+    commands: HashMap<BotCommand, u64>,
+    users: HashMap<BotCommand, HashMap<String, u64>>,
+}
+
+/// The last time a command was used.
+#[derive(Debug, Default)]
+pub struct Cooldowns {
+    pub commands: HashMap<BotCommand, Instant>,
+    pub users: HashMap<(BotCommand, String), Instant>,
+    /// When a user was last told they can't use a command
+    pub denied: HashMap<(BotCommand, String), Instant>,
+    /// When the last hint about an unknown command was sent
+    pub unknown: Option<Instant>,
+}
+
+impl Cooldowns {
+    /// Loads the cooldowns, starts without any when the file can't be read.
+    pub fn load<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let saved = match std::fs::read_to_string(path) {
+            Ok(file) => serde_json::from_str(&file).unwrap_or_else(|e| {
+                warn!(?e, "invalid cooldowns, starting without any");
+                SavedCooldowns::default()
+            }),
+            Err(_) => SavedCooldowns::default(),
+        };
+
+        let mut cooldowns = Self::default();
+
+        for (command, secs) in saved.commands {
+            if let Some(instant) = from_unix(secs) {
+                cooldowns.commands.insert(command, instant);
+            }
+        }
+
+        for (command, users) in saved.users {
+            for (user, secs) in users {
+                if let Some(instant) = from_unix(secs) {
+                    cooldowns.users.insert((command.to_owned(), user), instant);
+                }
+            }
+        }
+
+        cooldowns
+    }
+
+    /// Removes the cooldowns which ended and writes the rest to the file.
+    pub fn save(
+        &mut self,
+        path: &Path,
+        commands: &HashMap<BotCommand, config::CommandInformation>,
+    ) -> std::io::Result<()> {
+        let info = |command: &BotCommand| commands.get(command);
+        let running = |last: &Instant, secs: Option<u64>| matches!(secs, Some(secs) if last.elapsed() < Duration::from_secs(secs));
+
+        self.commands
+            .retain(|command, last| running(last, info(command).and_then(|i| i.cooldown_secs)));
+        self.users.retain(|(command, _), last| {
+            running(last, info(command).and_then(|i| i.user_cooldown_secs))
+        });
+
+        let mut saved = SavedCooldowns::default();
+
+        for (command, last) in &self.commands {
+            saved.commands.insert(command.to_owned(), to_unix(*last));
+        }
+
+        for ((command, user), last) in &self.users {
+            saved
+                .users
+                .entry(command.to_owned())
+                .or_default()
+                .insert(user.to_owned(), to_unix(*last));
+        }
+
+        let contents = serde_json::to_string_pretty(&saved)?;
+        crate::config::write_atomic(path, contents.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Checks if the command is on cooldown, see `record` for starting it.
+    pub fn is_on_cooldown(
+        &self,
+        command: &BotCommand,
+        info: &config::CommandInformation,
+        user: &str,
+    ) -> bool {
+        let now = Instant::now();
+        let user_key = (command.to_owned(), user.to_owned());
+
+        let active = |last: Option<&Instant>, secs: Option<u64>| match (last, secs) {
+            (Some(last), Some(secs)) => now.duration_since(*last) < Duration::from_secs(secs),
+            _ => false,
+        };
+
+        active(self.commands.get(command), info.cooldown_secs)
+            || active(self.users.get(&user_key), info.user_cooldown_secs)
+    }
+
+    /// Starts the cooldowns of a command which was used.
+    pub fn record(&mut self, command: &BotCommand, info: &config::CommandInformation, user: &str) {
+        let now = Instant::now();
+        let user_key = (command.to_owned(), user.to_owned());
+
+        if info.cooldown_secs.is_some() {
+            self.commands.insert(command.to_owned(), now);
+        }
+
+        if info.user_cooldown_secs.is_some() {
+            self.users.insert(user_key, now);
+        }
+    }
+
+    /// Checks if the user should be told they can't use the command, at most
+    /// once a minute so it can't flood chat.
+    pub fn report_denied(&mut self, command: &BotCommand, user: &str) -> bool {
+        let now = Instant::now();
+        self.denied
+            .retain(|_, last| now.duration_since(*last) < PERMISSION_DENIED_COOLDOWN);
+
+        let key = (command.to_owned(), user.to_owned());
+
+        if self.denied.contains_key(&key) {
+            return false;
+        }
+
+        self.denied.insert(key, now);
+
+        true
+    }
+
+    /// Checks if a hint about an unknown command can be sent, at most one
+    /// every `cooldown` in all channels.
+    pub fn report_unknown(&mut self, cooldown: Duration) -> bool {
+        let now = Instant::now();
+
+        if matches!(self.unknown, Some(last) if now.duration_since(last) < cooldown) {
+            return false;
+        }
+
+        self.unknown = Some(now);
+
+        true
+    }
 }
 
 impl CommandHandler {
-    pub async fn run(&self, mut messages: broadcast::Receiver<twitch::HandleMessage>) {
-        while let Ok(hm) = messages.recv().await {
+    pub async fn run(&mut self, mut messages: broadcast::Receiver<twitch::HandleMessage>) {
+        while let Ok(mut hm) = messages.recv().await {
             debug!("Handle message: {:?}", hm);
 
-            let mut split_message = hm.message.split_whitespace();
-
-            // You can't send a blank message.. hopefully
-            let command = split_message.next().unwrap().to_lowercase();
-            let (command, info) = match self.command(command) {
-                Some(c) => c,
-                None => continue,
+            // The badges can be missing, for example right after joining
+            self.twitch.apply_roles(&mut hm).await;
+
+            let mut split_message = hm.message.split_whitespace().peekable();
+
+            // Owned so the commands can be replaced by `Reload`
+            let found = match &hm.reward {
+                Some(redeemed) => self.commands.get_key_value(&redeemed.reward.command),
+                None => {
+                    let trigger = match parse_trigger(&mut split_message) {
+                        Some(t) => t,
+                        None => continue,
+                    };
+
+                    match self.command(&trigger) {
+                        Some(f) => Some(f),
+                        None => {
+                            self.unknown_command(&hm, &trigger).await;
+                            continue;
+                        }
+                    }
+                }
+            };
+            let (command, info) = match found {
+                Some((c, i)) => (c.to_owned(), i.to_owned()),
+                None => {
+                    debug!(reward = ?hm.reward, "the command of the reward isn't configured");
+                    continue;
+                }
             };
+            let (command, info) = (&command, &info);
             debug!(?command, "found command");
 
-            if !self.is_allowed_to_execute(&info.permission, &hm) {
+            if !info.enabled {
+                debug!(?command, "command disabled");
                 continue;
+            }
+
+            // A reward has its own permission, so a command can be redeemed
+            // by viewers who can't use it in chat
+            let permission = match &hm.reward {
+                Some(redeemed) => &redeemed.reward.permission,
+                None => &info.permission,
             };
 
-            info!("{} used command {:?}", hm.sender_name, command);
+            if !self.is_allowed_to_execute(permission, &hm)
+                || (command.is_broadcaster_only()
+                    && !self.is_allowed_to_execute(&Permission::Broadcaster, &hm))
+            {
+                debug!(?command, user = hm.sender_name, "permission denied");
+
+                let report = self
+                    .cooldowns
+                    .lock()
+                    .await
+                    .report_denied(command, &hm.sender_name);
+
+                if report {
+                    let message = self.reply(info, Err(CommandError::PermissionDenied));
+                    self.send(&hm.channel_name, message).await;
+                }
+
+                continue;
+            }
+
+            if let Some(Err(e)) = hm.reward.as_ref().map(|r| self.reward_input(r)) {
+                let message = self.reply(info, Err(e));
+                self.send(&hm.channel_name, message).await;
+                continue;
+            }
+
+            if self.read_only && !command.is_read_only() {
+                debug!(?command, "read-only mode");
+                let message = self.templates.get("read_only");
+                self.send(&hm.channel_name, message).await;
+                continue;
+            }
+
+            // Admins, moderators and above bypass the cooldown
+            let cooldown = !self.is_allowed_to_execute(&Permission::Moderator, &hm)
+                && (info.cooldown_secs.is_some() || info.user_cooldown_secs.is_some());
+
+            if cooldown
+                && self
+                    .cooldowns
+                    .lock()
+                    .await
+                    .is_on_cooldown(command, info, &hm.sender_name)
+            {
+                debug!(?command, "command on cooldown");
+                continue;
+            }
+
+            info!(
+                ?command,
+                user = hm.sender_name,
+                channel = hm.channel_name,
+                "Command used"
+            );
+
+            self.usage.lock().await.add(command, &hm.sender_name);
+
+            // Only commands which run start the cooldown, not the ones which
+            // fail because the BELABOX is offline or wait for a confirmation
+            if cooldown && matches!(command, BotCommand::Reload | BotCommand::ReadOnly) {
+                self.start_cooldown(command, info, &hm.sender_name).await;
+            }
+
+            if command == &BotCommand::Reload {
+                let message = self.reload();
+                self.send(&hm.channel_name, message).await;
+                continue;
+            }
 
-            if !{ self.bela_state.read().await.online } {
-                self.send("Offline :(".to_string()).await;
+            if command == &BotCommand::ReadOnly {
+                let response = self.set_read_only(split_message.next());
+                let message = self.reply(info, response);
+                self.send(&hm.channel_name, message).await;
                 continue;
             }
 
+            let device = self.device(&mut split_message);
+
+            // These don't need the BELABOX to be online
+            let needs_online = !matches!(
+                command,
+                BotCommand::BotUptime | BotCommand::Help | BotCommand::Top | BotCommand::Version
+            );
+
+            if needs_online {
+                if let Some(e) = device.unavailable().await {
+                    let message = device.response(self.reply(info, Err(e)));
+                    self.send(&hm.channel_name, message).await;
+                    continue;
+                }
+            }
+
+            if matches!(command, BotCommand::Poweroff | BotCommand::Restart) {
+                let confirmation = self
+                    .confirmation(command, info, device, split_message.next())
+                    .await;
+
+                if let Some(message) = confirmation {
+                    self.send(&hm.channel_name, device.response(message)).await;
+                    continue;
+                }
+            }
+
+            if cooldown {
+                self.start_cooldown(command, info, &hm.sender_name).await;
+            }
+
+            let channel = hm.channel_name.as_str();
             let response = match command {
-                BotCommand::AudioDelay => self.audio_delay(split_message.next()).await,
-                BotCommand::AudioSrc => self.audio_src(split_message).await,
-                BotCommand::Bitrate => self.bitrate(split_message.next()).await,
-                BotCommand::Latency => self.latency(split_message.next()).await,
-                BotCommand::Network => self.network(split_message.next()).await,
-                BotCommand::Pipeline => self.pipeline(split_message).await,
-                BotCommand::Poweroff => self.poweroff().await,
-                BotCommand::Restart => self.restart().await,
-                BotCommand::Sensor => self.sensor().await,
-                BotCommand::Start => self.start().await,
-                BotCommand::Stats => self.stats().await,
-                BotCommand::Stop => self.stop().await,
+                BotCommand::Audio => device.audio(channel, split_message).await,
+                BotCommand::AudioDelay => device.audio_delay(channel, split_message.next()).await,
+                BotCommand::AudioSrc => device.audio_src(channel, split_message).await,
+                BotCommand::Bitrate => {
+                    device
+                        .bitrate(split_message.next(), split_message.next())
+                        .await
+                }
+                BotCommand::BotUptime => Ok(self.bot_uptime()),
+                BotCommand::Connection => device.connection().await,
+                BotCommand::Help => Ok(self.help(&hm)),
+                BotCommand::History => device.history().await,
+                BotCommand::Label => self.label(device, split_message).await,
+                BotCommand::Latency => device.latency(channel, split_message.next()).await,
+                BotCommand::ModemRestart => {
+                    device.modem_restart(channel, split_message.next()).await
+                }
+                BotCommand::ModemToggle => {
+                    device
+                        .modem_toggle(split_message.next(), split_message.next())
+                        .await
+                }
+                BotCommand::Network => device.network(split_message.next()).await,
+                BotCommand::Output => device.output(channel, split_message.next()).await,
+                BotCommand::Pipeline => device.pipeline(channel, split_message).await,
+                BotCommand::Poweroff => device.poweroff().await,
+                BotCommand::Raw if !self.allow_raw => Ok(self.templates.get("raw_disabled")),
+                BotCommand::Raw => {
+                    let message = split_message.collect::<Vec<&str>>().join(" ");
+                    device.raw(&hm.sender_name, &message).await
+                }
+                // Handled before the device is chosen
+                BotCommand::ReadOnly | BotCommand::Reload => continue,
+                BotCommand::Restart => device.restart(channel).await,
+                BotCommand::Sensor => device.sensor().await,
+                BotCommand::Signal => device.signal().await,
+                BotCommand::Start => {
+                    let relay = split_message.collect::<Vec<&str>>().join(" ");
+
+                    if relay.is_empty() {
+                        device.start().await
+                    } else {
+                        device.start_on_relay(&relay).await
+                    }
+                }
+                BotCommand::Stats => device.stats().await,
+                BotCommand::Stop => device.stop_mode(split_message.next()).await,
+                BotCommand::Top => Ok(self.top().await),
+                BotCommand::Version => Ok(self.version().await),
             };
 
-            match response {
-                Ok(message) => self.send(message).await,
-                Err(e) => self.send(format!("Error {}", e)).await,
+            if info.quiet_success && response.is_ok() && !command.is_read_only() {
+                debug!(?command, "quiet success, not replying");
+                continue;
+            }
+
+            let message = device.response(self.reply(info, response));
+
+            match info.reply_mode {
+                ReplyMode::Public => self.send(channel, message).await,
+                ReplyMode::Whisper => {
+                    // No fallback to chat, the reply might be private
+                    if let Err(e) = self.twitch.whisper(&hm.sender_id, message).await {
+                        error!(?e, user = hm.sender_name, "error whispering the reply");
+                    }
+                }
             }
         }
     }
 
-    async fn send(&self, message: String) {
-        if let Err(e) = self.twitch.send(message).await {
-            error!(?e, "error sending message to twitch");
+    /// Checks that the input of a reward is a number within its `min` and
+    /// `max`, any input is fine without them.
+    fn reward_input(&self, redeemed: &twitch::Redeemed) -> Result<(), CommandError> {
+        let (min, max) = (redeemed.reward.min, redeemed.reward.max);
+
+        if min.is_none() && max.is_none() {
+            return Ok(());
+        }
+
+        let value = redeemed.input.parse::<i64>().map_err(|_| {
+            CommandError::InvalidArgument(
+                self.templates
+                    .render("invalid_number", &[("value", &redeemed.input)]),
+            )
+        })?;
+
+        let (min, max) = (min.unwrap_or(i64::MIN), max.unwrap_or(i64::MAX));
+
+        if !(min..=max).contains(&value) {
+            return Err(CommandError::InvalidArgument(self.templates.render(
+                "invalid_range",
+                &[("value", &value), ("min", &min), ("max", &max)],
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Starts the cooldowns of the command and saves them.
+    async fn start_cooldown(
+        &self,
+        command: &BotCommand,
+        info: &config::CommandInformation,
+        user: &str,
+    ) {
+        self.cooldowns.lock().await.record(command, info, user);
+        self.save_cooldowns().await;
+    }
+
+    async fn save_cooldowns(&self) {
+        let path = match &self.cooldown_file {
+            Some(p) => p,
+            None => return,
+        };
+
+        if let Err(e) = self.cooldowns.lock().await.save(path, &self.commands) {
+            error!(?e, "failed to save the cooldowns");
+        }
+    }
+
+    /// Loads the config file again and applies the settings which don't need
+    /// new connections, such as the commands, permissions and monitor.
+    fn reload(&mut self) -> String {
+        let path = match &self.path {
+            Some(p) => p.to_owned(),
+            None => return self.templates.get("reload_no_config"),
+        };
+
+        let settings = match config::Settings::load(&path) {
+            Ok(s) => s,
+            Err(e) => {
+                error!(?e, "failed to reload the config");
+                return self.templates.render("reload_failed", &[("error", &e)]);
+            }
+        };
+
+        self.commands = settings.commands;
+        self.admins = settings.twitch.admins;
+        self.user_overrides = settings.twitch.user_overrides;
+        self.groups = settings.twitch.groups;
+        self.confirm_secs = settings.safety.confirm_secs;
+        self.allow_raw = settings.safety.allow_raw;
+        self.read_only = settings.safety.read_only;
+        self.unknown_commands = settings.unknown_commands;
+        self.check_updates = settings.check_updates;
+        self.cooldown_file = settings.safety.cooldown_file;
+        self.templates.replace(settings.messages);
+        self.monitor.send_replace(settings.belabox.monitor);
+
+        info!(?path, "Reloaded the config");
+
+        self.templates.get("reload_done")
+    }
+
+    /// Turns read-only mode on or off, toggles it without an argument. Not
+    /// saved to the config, restarts and `Reload` use `safety.read_only`.
+    fn set_read_only(&mut self, arg: Option<&str>) -> CommandResult {
+        let read_only = match arg.map(|a| a.to_lowercase()).as_deref() {
+            None => !self.read_only,
+            Some("on") => true,
+            Some("off") => false,
+            Some(_) => {
+                return Err(CommandError::InvalidArgument(
+                    self.templates.get("read_only_invalid"),
+                ))
+            }
+        };
+
+        self.read_only = read_only;
+        info!(read_only, "Changed read-only mode");
+
+        if read_only {
+            Ok(self.templates.get("read_only_enabled"))
+        } else {
+            Ok(self.templates.get("read_only_disabled"))
+        }
+    }
+
+    /// The chat message of a command response, wrong arguments also show
+    /// the usage of the command.
+    fn reply(&self, info: &config::CommandInformation, response: CommandResult) -> String {
+        let e = match response {
+            Ok(message) => return message,
+            Err(e) => e,
+        };
+
+        let key = e.message_key();
+
+        match e {
+            CommandError::InvalidArgument(message) => match &info.usage {
+                Some(usage) if !usage.is_empty() => self.templates.render(
+                    key,
+                    &[
+                        ("message", &message),
+                        ("usage", &usage.replace("{command}", &info.command)),
+                    ],
+                ),
+                _ => message,
+            },
+            CommandError::PermissionDenied => {
+                self.templates.render(key, &[("command", &info.command)])
+            }
+            CommandError::Failed(e) => {
+                error!(?e, command = info.command, "command failed");
+                self.templates.render(key, &[("error", &error_chain(&e))])
+            }
+            e => self.templates.render(key, &[("error", &e)]),
+        }
+    }
+
+    /// How long the bot has been running, to spot restart loops.
+    fn bot_uptime(&self) -> String {
+        self.templates.render(
+            "bot_uptime",
+            &[
+                ("version", &update::VERSION),
+                ("uptime", &format_duration(self.started.elapsed())),
+            ],
+        )
+    }
+
+    /// The version and commit, and a newer release when `check_updates` is
+    /// enabled.
+    async fn version(&self) -> String {
+        let newer = if self.check_updates {
+            self.update_check.lock().await.newer_version().await
+        } else {
+            None
+        };
+
+        match newer {
+            Some(latest) => self.templates.render(
+                "version_update",
+                &[
+                    ("version", &update::VERSION),
+                    ("commit", &update::GIT_HASH),
+                    ("latest", &latest),
+                ],
+            ),
+            None => self.templates.render(
+                "version",
+                &[("version", &update::VERSION), ("commit", &update::GIT_HASH)],
+            ),
+        }
+    }
+
+    /// Gives an interface of the device a custom name, without a name the
+    /// custom name is removed. The config is saved first so the name is
+    /// kept after a restart.
+    async fn label<'a, I>(&self, device: &Device, mut args: I) -> CommandResult
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let interface = match args.next() {
+            Some(i) => i.to_lowercase(),
+            None => {
+                return Err(CommandError::InvalidArgument(
+                    self.templates.get("interface_missing"),
+                ))
+            }
+        };
+
+        let name = args.collect::<Vec<&str>>().join(" ");
+        let name = name.trim_matches(|c| c == '"' || c == '\'').trim();
+
+        let netifs = match device.bela_state.read().await.netif.to_owned() {
+            Some(n) => n,
+            None => return Ok(self.templates.get("interfaces_unavailable")),
+        };
+
+        let (interface_name, netif) = match device.find_interface(&interface, &netifs) {
+            Some(i) => i,
+            None => return Ok(self.templates.get("interface_not_found")),
+        };
+        let display_name = device.interface_name(interface_name, netif);
+
+        let taken = netifs.iter().any(|(other, other_netif)| {
+            other != interface_name
+                && (other.eq_ignore_ascii_case(name)
+                    || device
+                        .interface_name(other, other_netif)
+                        .eq_ignore_ascii_case(name))
+        });
+
+        if taken {
+            return Ok(self.templates.render("label_taken", &[("name", &name)]));
+        }
+
+        // A name based on the IP would win over the new one
+        let relabel = |names: &mut HashMap<String, String>| {
+            names.remove(&netif.ip);
+            names.remove(interface_name);
+
+            if !name.is_empty() {
+                names.insert(interface_name.to_owned(), name.to_owned());
+            }
+        };
+
+        if let Some(path) = &self.path {
+            let saved = config::Settings::update(path, |settings| {
+                let names = match &device.name {
+                    None => &mut settings.belabox.custom_interface_name,
+                    Some(device) => match settings.belabox.devices.get_mut(device) {
+                        Some(d) => &mut d.custom_interface_name,
+                        None => return,
+                    },
+                };
+
+                relabel(names);
+            });
+
+            if let Err(e) = saved {
+                error!(?e, "failed to save the interface name");
+                return Ok(self.templates.render("label_not_saved", &[("error", &e)]));
+            }
+        }
+
+        relabel(&mut device.custom_interface_name.write().unwrap());
+        info!(
+            interface = interface_name,
+            name, "Changed the interface name"
+        );
+
+        if name.is_empty() {
+            Ok(self
+                .templates
+                .render("label_removed", &[("interface", interface_name)]))
+        } else {
+            Ok(self.templates.render(
+                "label_changed",
+                &[("interface", &display_name), ("name", &name)],
+            ))
         }
     }
 
-    fn command(
+    /// Lists the most used commands and users of this session.
+    async fn top(&self) -> String {
+        let usage = self.usage.lock().await;
+
+        let commands = usage::top(&usage.session.commands, TOP_AMOUNT)
+            .into_iter()
+            .map(|(command, count)| {
+                let name = self
+                    .commands
+                    .get(command)
+                    .map(|info| info.command.to_owned())
+                    .unwrap_or_else(|| format!("{:?}", command));
+
+                format!("{} {}", name, count)
+            })
+            .collect::<Vec<String>>();
+
+        if commands.is_empty() {
+            return self.templates.get("top_empty");
+        }
+
+        let users = usage::top(&usage.session.users, TOP_AMOUNT)
+            .into_iter()
+            .map(|(user, count)| format!("{} {}", user, count))
+            .collect::<Vec<String>>();
+
+        self.templates.render(
+            "top",
+            &[
+                ("commands", &commands.join(", ")),
+                ("users", &users.join(", ")),
+            ],
+        )
+    }
+
+    /// Asks to repeat a destructive command with `confirm`, returns the reply
+    /// when the command should not run yet.
+    async fn confirmation(
         &self,
-        command: String,
-    ) -> Option<(&config::BotCommand, &config::CommandInformation)> {
+        command: &BotCommand,
+        info: &config::CommandInformation,
+        device: &Device,
+        arg: Option<&str>,
+    ) -> Option<String> {
+        if self.confirm_secs == 0 {
+            return None;
+        }
+
+        let window = Duration::from_secs(self.confirm_secs);
+        let confirmed = matches!(arg, Some(a) if a.eq_ignore_ascii_case("confirm"));
+
+        {
+            let mut lock = device.bela_state.write().await;
+            let pending = lock.pending_confirmations.remove(command);
+
+            if confirmed && matches!(pending, Some(p) if p.elapsed() < window) {
+                return None;
+            }
+
+            lock.pending_confirmations
+                .insert(command.to_owned(), Instant::now());
+        }
+
+        let trigger = match &device.name {
+            Some(name) => format!("{} {} confirm", info.command, name),
+            None => format!("{} confirm", info.command),
+        };
+
+        Some(self.templates.render(
+            "confirm",
+            &[("command", &trigger), ("secs", &self.confirm_secs)],
+        ))
+    }
+
+    /// Gets the device targeted by the first argument, defaults to the primary
+    /// device. The argument is consumed when it names a device.
+    fn device<'a, I>(&self, args: &mut Peekable<I>) -> &Device
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let named = args.peek().and_then(|arg| {
+            self.devices
+                .iter()
+                .skip(1)
+                .find(|d| matches!(&d.name, Some(name) if name.eq_ignore_ascii_case(arg)))
+        });
+
+        match named {
+            Some(device) => {
+                args.next();
+                device
+            }
+            None => &self.devices[0],
+        }
+    }
+
+    async fn send(&self, channel: &str, message: String) {
+        if let Err(e) = self.twitch.send_to(channel, message).await {
+            error!(?e, "error sending message to twitch");
+        }
+    }
+
+    fn command(&self, command: &str) -> Option<(&config::BotCommand, &config::CommandInformation)> {
         self.commands
             .iter()
-            .find(|(_, info)| command == info.command)
+            .find(|(_, info)| info.is_trigger(command))
+    }
+
+    /// Suggests the command one typo away when `unknown_commands.suggest` is
+    /// enabled, otherwise points to `Help` when the trigger looks like one of
+    /// the commands, for example `!bbstats` when the commands start with
+    /// `!bb`. Silent unless `unknown_commands.hint` is enabled.
+    async fn unknown_command(&self, hm: &twitch::HandleMessage, trigger: &str) {
+        if self.unknown_commands.suggest && self.suggest_command(hm, trigger).await {
+            return;
+        }
+
+        if !self.unknown_commands.hint {
+            return;
+        }
+
+        let help = match self.commands.get(&BotCommand::Help) {
+            Some(info) if info.enabled && self.is_allowed_to_execute(&info.permission, hm) => {
+                &info.command
+            }
+            _ => return,
+        };
+
+        let triggers = self
+            .commands
+            .values()
+            .filter(|info| info.enabled)
+            .map(|info| info.command.as_str());
+
+        // Without a shared start every chat message would get a hint
+        let prefix = common_prefix(triggers);
+        if prefix.is_empty() || !trigger.starts_with(prefix) {
+            return;
+        }
+
+        let cooldown = Duration::from_secs(self.unknown_commands.cooldown_secs);
+        if !self.cooldowns.lock().await.report_unknown(cooldown) {
+            debug!(trigger, "unknown command hint on cooldown");
+            return;
+        }
+
+        let message = self
+            .templates
+            .render("unknown_command", &[("command", &trigger), ("help", help)]);
+        self.send(&hm.channel_name, message).await;
+    }
+
+    /// Replies with the command the trigger is a typo of, only commands the
+    /// user can use are suggested. Returns `false` when there is none.
+    async fn suggest_command(&self, hm: &twitch::HandleMessage, trigger: &str) -> bool {
+        let usable = self
+            .commands
+            .iter()
+            .filter(|(_, info)| info.enabled && self.is_allowed_to_execute(&info.permission, hm))
+            .filter(|(command, _)| {
+                !command.is_broadcaster_only()
+                    || self.is_allowed_to_execute(&Permission::Broadcaster, hm)
+            })
+            .flat_map(|(_, info)| {
+                std::iter::once(&info.command)
+                    .chain(&info.aliases)
+                    .map(move |t| (t.to_lowercase(), info))
+            })
+            .collect::<Vec<_>>();
+
+        let suggestion = match typo_of(trigger, usable.iter().map(|(t, _)| t.as_str())) {
+            Some(s) => s,
+            None => return false,
+        };
+
+        let info = match usable.iter().find(|(t, _)| t == suggestion) {
+            Some((_, info)) => info,
+            None => return false,
+        };
+
+        let cooldown = Duration::from_secs(self.unknown_commands.cooldown_secs);
+        if !self.cooldowns.lock().await.report_unknown(cooldown) {
+            debug!(trigger, "command suggestion on cooldown");
+            // Still counts as handled, a hint would be on cooldown as well
+            return true;
+        }
+
+        let message = self
+            .templates
+            .render("command_suggestion", &[("command", &suggestion)]);
+
+        match info.reply_mode {
+            ReplyMode::Public => self.send(&hm.channel_name, message).await,
+            ReplyMode::Whisper => {
+                if let Err(e) = self.twitch.whisper(&hm.sender_id, message).await {
+                    error!(?e, user = hm.sender_name, "error whispering the suggestion");
+                }
+            }
+        }
+
+        true
+    }
+
+    fn is_admin(&self, user: &str) -> bool {
+        self.admins.iter().any(|a| a.eq_ignore_ascii_case(user))
     }
 
     fn is_allowed_to_execute(
@@ -91,25 +1002,169 @@ impl CommandHandler {
     ) -> bool {
         let twitch::HandleMessage {
             sender_name,
-            broadcaster,
             moderator,
             vip,
+            subscriber,
             ..
         } = handle_message;
 
-        let broadcaster = *broadcaster || self.admins.contains(sender_name);
-        let moderator = broadcaster || *moderator;
-        let vip = moderator || *vip;
+        // Admins and the broadcaster of the channel can do everything, even
+        // when a user override says otherwise
+        if self.is_admin(sender_name) || handle_message.is_broadcaster() {
+            return true;
+        }
+
+        // Explicit user permissions win over the roles, a user override can
+        // put a user in a group as well
+        let role = match self.user_overrides.get(sender_name) {
+            Some(p) => p.to_owned(),
+            None if *moderator => Permission::Moderator,
+            None if *vip => Permission::Vip,
+            None if *subscriber => Permission::Subscriber,
+            None => Permission::Public,
+        };
+
+        if permission.satisfies(&role) {
+            return true;
+        }
+
+        let group = match permission {
+            Permission::Group(g) => g,
+            _ => return false,
+        };
+
+        match self.groups.get(group) {
+            Some(users) => users.iter().any(|u| u.eq_ignore_ascii_case(sender_name)),
+            None => {
+                debug!(group, "unknown permission group");
+                false
+            }
+        }
+    }
+
+    /// Lists the commands the user is allowed to use.
+    pub fn help(&self, handle_message: &twitch::HandleMessage) -> String {
+        let mut commands = self
+            .commands
+            .iter()
+            .filter(|(command, _)| !self.read_only || command.is_read_only())
+            .map(|(_, info)| info)
+            .filter(|info| info.enabled)
+            .filter(|info| self.is_allowed_to_execute(&info.permission, handle_message))
+            .map(|info| info.command.as_str())
+            .collect::<Vec<&str>>();
+
+        commands.sort_unstable();
+
+        self.templates
+            .render("help", &[("commands", &commands.join(", "))])
+    }
+}
+
+impl Device {
+    /// Sends a message before the command is done.
+    async fn send(&self, channel: &str, message: String) {
+        if let Err(e) = self.twitch.send_to(channel, self.response(message)).await {
+            error!(?e, "error sending message to twitch");
+        }
+    }
+
+    /// The message key explaining why commands can't be sent to the BELABOX,
+    /// `None` when they can.
+    async fn unavailable(&self) -> Option<CommandError> {
+        let state = self.bela_state.read().await;
+
+        if !state.connected {
+            return Some(CommandError::Disconnected);
+        }
+
+        if !state.online {
+            return Some(CommandError::Offline);
+        }
+
+        match state.last_message {
+            Some(last) if last.elapsed().as_secs() <= self.monitor.borrow().stale_secs => None,
+            _ => Some(CommandError::Stale),
+        }
+    }
+
+    /// Prefixes the message with the name of the device, and marks it when
+    /// nothing was sent to the BELABOX.
+    fn response(&self, message: String) -> String {
+        let message = match &self.name {
+            Some(name) => format!("{}: {}", name, message),
+            None => message,
+        };
+
+        if self.belabox.dry_run {
+            return self.templates.render("dry_run", &[("message", &message)]);
+        }
+
+        message
+    }
+
+    /// Finds an interface by its name or custom name.
+    fn find_interface<'a>(
+        &self,
+        name: &str,
+        netifs: &'a HashMap<String, belabox::messages::Netif>,
+    ) -> Option<(&'a String, &'a belabox::messages::Netif)> {
+        let mut interface = netifs.get_key_value(name);
+
+        if interface.is_none() {
+            // get iterface name based on custom name
+            let mut possible_ip = None;
+
+            let names = self.custom_interface_name.read().unwrap();
+
+            // Custom name based on interface
+            for (original, custom) in names.iter() {
+                if name == custom.to_lowercase() {
+                    interface = netifs.get_key_value(original);
+                    possible_ip = Some(original);
+                    break;
+                }
+            }
 
-        match permission {
-            Permission::Broadcaster => broadcaster,
-            Permission::Moderator => moderator,
-            Permission::Vip => vip,
-            Permission::Public => true,
+            // Custom name based on ip
+            if let (None, Some(possible_ip)) = (interface, possible_ip) {
+                for (k, v) in netifs {
+                    if &v.ip == possible_ip {
+                        interface = netifs.get_key_value(k);
+                        break;
+                    }
+                }
+            }
         }
+
+        interface
     }
 
-    pub async fn start(&self) -> Result<String> {
+    /// The custom name of an interface, or its original name.
+    pub(crate) fn interface_name(&self, name: &str, netif: &belabox::messages::Netif) -> String {
+        let names = self.custom_interface_name.read().unwrap();
+
+        // A custom name based on the IP wins over one based on the interface
+        names
+            .get(&netif.ip)
+            .or_else(|| names.get(name))
+            .map(|n| n.as_str())
+            .unwrap_or(name)
+            .to_owned()
+    }
+
+    /// Lists the names of the interfaces which can be used in commands.
+    fn interface_names(&self, netifs: &HashMap<String, belabox::messages::Netif>) -> String {
+        let mut names = netifs
+            .iter()
+            .map(|(name, netif)| self.interface_name(name, netif))
+            .collect::<Vec<String>>();
+
+        names.sort_unstable();
+        names.join(", ")
+    }
+
+    pub async fn start(&self) -> CommandResult {
         let (config, is_streaming) = {
             let read = self.bela_state.read().await;
             (read.config.clone(), read.is_streaming)
@@ -118,124 +1173,371 @@ impl CommandHandler {
         let config = match config {
             Some(c) => c,
             None => {
-                return Ok("Error starting BELABOX".to_string());
+                return Ok(self.templates.get("start_error"));
             }
         };
 
         if is_streaming {
-            return Ok("Error already streaming".to_string());
+            return Ok(self.templates.get("already_streaming"));
+        }
+
+        let request = belabox::requests::Start::from(config);
+        self.belabox.start(request).await?;
+
+        Ok(self.templates.get("start"))
+    }
+
+    /// Starts the stream on the relay server with the id or name.
+    pub async fn start_on_relay(&self, relay: &str) -> CommandResult {
+        let (relays, is_streaming) = {
+            let read = self.bela_state.read().await;
+            (read.relays.to_owned(), read.is_streaming)
+        };
+
+        if is_streaming {
+            return Ok(self.templates.get("already_streaming"));
         }
 
+        let servers = match relays {
+            Some(r) if !r.servers.is_empty() => r.servers,
+            _ => return Ok(self.templates.get("relays_unavailable")),
+        };
+
+        let query = relay.to_lowercase();
+        let found = servers
+            .iter()
+            .find(|(id, s)| id.to_lowercase() == query || s.name.to_lowercase() == query)
+            .or_else(|| {
+                let mut matches = servers
+                    .iter()
+                    .filter(|(_, s)| s.name.to_lowercase().contains(&query));
+
+                // Only use a partial match when it's the only one
+                match (matches.next(), matches.next()) {
+                    (Some(m), None) => Some(m),
+                    _ => None,
+                }
+            });
+
+        let (id, server) = match found {
+            Some(f) => f,
+            None => {
+                let mut names = servers
+                    .values()
+                    .map(|s| s.name.as_str())
+                    .collect::<Vec<&str>>();
+                names.sort_unstable();
+
+                return Ok(self
+                    .templates
+                    .render("relay_not_found", &[("relays", &names.join(", "))]));
+            }
+        };
+
+        let config = {
+            let mut lock = self.bela_state.write().await;
+
+            match &mut lock.config {
+                Some(config) => {
+                    config.relay_server = Some(id.to_owned());
+                    config.clone()
+                }
+                None => return Ok(self.templates.get("start_error")),
+            }
+        };
+
         let request = belabox::requests::Start::from(config);
         self.belabox.start(request).await?;
 
-        Ok("Starting BELABOX".to_string())
+        Ok(self
+            .templates
+            .render("start_relay", &[("relay", &server.name)]))
     }
 
-    pub async fn stop(&self) -> Result<String> {
+    pub async fn stop(&self) -> CommandResult {
         if !{ self.bela_state.read().await.is_streaming } {
-            return Ok("Error not streaming".to_string());
+            return Ok(self.templates.get("not_streaming"));
         }
 
         self.belabox.stop().await?;
-        Ok("Stopping BELABOX".to_string())
+        Ok(self.templates.get("stop"))
     }
 
-    pub async fn stats(&self) -> Result<String> {
-        let (netifs, ups) = {
+    /// `Stop` from chat, `idle` says that the BELABOX stays powered on.
+    pub async fn stop_mode(&self, mode: Option<&str>) -> CommandResult {
+        match mode {
+            None => self.stop().await,
+            // The BELABOX protocol has no low-power state, only power off
+            Some(m) if m.eq_ignore_ascii_case("idle") => {
+                if !{ self.bela_state.read().await.is_streaming } {
+                    return Ok(self.templates.get("not_streaming"));
+                }
+
+                self.belabox.stop().await?;
+                Ok(self.templates.get("stop_idle"))
+            }
+            Some(_) => Err(CommandError::InvalidArgument(
+                self.templates.get("stop_invalid"),
+            )),
+        }
+    }
+
+    pub async fn stats(&self) -> CommandResult {
+        let (netifs, ups, session) = {
             let read = self.bela_state.read().await;
-            (read.netif.to_owned(), read.notify_ups)
+            (
+                read.netif.to_owned(),
+                read.notify_ups,
+                read.session.to_owned(),
+            )
         };
 
         let mut total_bitrate = 0;
         let mut interfaces = netifs
             .iter()
             .flatten()
-            .map(|(mut name, i)| {
-                let value = if i.enabled {
+            .map(|(name, i)| {
+                let interface = self.interface_name(name, i);
+
+                if i.enabled {
                     let bitrate = (i.tp * 8) / 1024;
                     total_bitrate += bitrate;
-                    format!("{} kbps", bitrate)
-                } else {
-                    "disabled".to_string()
-                };
-
-                // Check if custom interface name based on interface
-                if let Some(custom) = self.custom_interface_name.get(name) {
-                    name = custom;
-                }
 
-                // Check if custom interface name based on IP
-                if let Some(custom) = self.custom_interface_name.get(&i.ip) {
-                    name = custom;
+                    self.templates.render(
+                        "stats_interface",
+                        &[("interface", &interface), ("bitrate", &bitrate)],
+                    )
+                } else {
+                    self.templates
+                        .render("stats_interface_disabled", &[("interface", &interface)])
                 }
-
-                format!("{}: {}", name, value)
             })
             .collect::<Vec<String>>();
 
         // Sort interfaces because they like to move around
         interfaces.sort();
 
-        let mut msg = format!("{}, Total: {} kbps", interfaces.join(", "), total_bitrate);
+        let mut msg = self.templates.render(
+            "stats",
+            &[
+                ("interfaces", &interfaces.join(", ")),
+                ("bitrate", &total_bitrate),
+            ],
+        );
 
         if let Some(connected) = ups {
-            let a = if !connected { "not" } else { "" };
-            let _ = write!(msg, ", UPS: {} charging", a);
+            let ups = if connected {
+                self.templates.get("ups_charging")
+            } else {
+                self.templates.get("ups_not_charging")
+            };
+
+            msg = self
+                .templates
+                .render("stats_ups", &[("stats", &msg), ("ups", &ups)]);
+        }
+
+        if let Some(session) = session {
+            let bitrate = match session.average_bitrate() {
+                Some(b) => b.to_string(),
+                None => self.templates.get("unknown"),
+            };
+
+            msg = self.templates.render(
+                "stats_session",
+                &[
+                    ("stats", &msg),
+                    ("uptime", &format_duration(session.started.elapsed())),
+                    ("bitrate", &bitrate),
+                ],
+            );
         }
 
         Ok(msg)
     }
 
-    pub async fn restart(&self) -> Result<String> {
-        let is_streaming = {
+    /// Reboots the BELABOX, a stream which was running is started again and
+    /// the channel is told when it's back or when it takes too long.
+    pub async fn restart(&self, channel: &str) -> CommandResult {
+        let resuming = {
             let mut lock = self.bela_state.write().await;
 
             if lock.restart {
-                return Err(Error::Belabox(BelaboxError::AlreadyRestarting));
+                return Err(BelaboxError::AlreadyRestarting.into());
             }
 
             if lock.is_streaming {
                 lock.restart = true;
+                lock.resuming = Some(Instant::now());
+                lock.resuming
+            } else {
+                None
             }
-
-            lock.is_streaming
         };
 
-        if is_streaming {
+        if resuming.is_some() {
             self.belabox.stop().await?;
         }
 
         self.belabox.restart().await?;
-        Ok("Rebooting BELABOX".to_string())
+
+        if let Some(started) = resuming {
+            tokio::spawn(self.to_owned().wait_for_resume(channel.to_owned(), started));
+        }
+
+        Ok(self.templates.get("restart"))
+    }
+
+    /// Waits for the stream to run again after `restart`.
+    async fn wait_for_resume(self, channel: String, started: Instant) {
+        let timeout = Duration::from_secs(self.monitor.borrow().restart_timeout_secs);
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+
+        let message = loop {
+            interval.tick().await;
+
+            let mut lock = self.bela_state.write().await;
+
+            // Another restart took over
+            if lock.resuming != Some(started) {
+                return;
+            }
+
+            if !lock.restart && lock.is_streaming {
+                lock.resuming = None;
+                break self.templates.get("restart_live");
+            }
+
+            if started.elapsed() >= timeout {
+                lock.resuming = None;
+                break self
+                    .templates
+                    .render("restart_timeout", &[("seconds", &timeout.as_secs())]);
+            }
+        };
+
+        self.send(&channel, message).await;
     }
 
-    pub async fn poweroff(&self) -> Result<String> {
+    pub async fn poweroff(&self) -> CommandResult {
         self.belabox.poweroff().await?;
-        Ok("Powering off BELABOX".to_string())
+        Ok(self.templates.get("poweroff"))
     }
 
-    pub async fn bitrate(&self, bitrate: Option<&str>) -> Result<String> {
+    /// Sends a JSON object to the BELABOX as is and replies with the first
+    /// message that has one of its keys.
+    pub async fn raw(&self, user: &str, message: &str) -> CommandResult {
+        let keys = match serde_json::from_str::<serde_json::Value>(message) {
+            Ok(serde_json::Value::Object(object)) => object.keys().cloned().collect::<Vec<_>>(),
+            _ => {
+                return Err(CommandError::InvalidArgument(
+                    self.templates.get("raw_invalid"),
+                ))
+            }
+        };
+
+        info!(user, message, "Sending raw message to the BELABOX");
+
+        // Subscribe before sending so the reply can't be missed
+        let mut messages = self.belabox.raw_stream()?;
+        self.belabox.send_raw(message.to_owned()).await?;
+
+        if self.belabox.dry_run {
+            return Ok(self.templates.get("raw_sent"));
+        }
+
+        let response = async {
+            loop {
+                match messages.recv().await {
+                    Ok(text) => {
+                        let is_response = serde_json::from_str::<serde_json::Value>(&text)
+                            .ok()
+                            .and_then(|v| v.as_object().cloned())
+                            .map(|o| keys.iter().any(|k| o.contains_key(k)))
+                            .unwrap_or(false);
+
+                        if is_response {
+                            return Some(text);
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        };
+
+        let response = match tokio::time::timeout(RAW_TIMEOUT, response).await {
+            Ok(Some(mut text)) => {
+                if let Some((end, _)) = text.char_indices().nth(RAW_RESPONSE_MAX_CHARS) {
+                    text.truncate(end);
+                    text.push('…');
+                }
+
+                self.templates
+                    .render("raw_response", &[("response", &text)])
+            }
+            Ok(None) | Err(_) => self.templates.get("raw_sent"),
+        };
+
+        Ok(response)
+    }
+
+    /// Shows or sets the max bitrate, `fixed` followed by a bitrate sets it
+    /// as well.
+    pub async fn bitrate(&self, bitrate: Option<&str>, value: Option<&str>) -> CommandResult {
         let bitrate = match bitrate {
+            // The BELABOX protocol has no bitrate mode, the encoder always
+            // adapts the bitrate up to the max bitrate
+            Some(mode) if mode.eq_ignore_ascii_case("auto") => {
+                return Ok(self.templates.get("bitrate_adaptive"));
+            }
+            Some(mode) if mode.eq_ignore_ascii_case("fixed") => match value {
+                Some(b) => b,
+                None => {
+                    return Err(CommandError::InvalidArgument(
+                        self.templates.get("bitrate_fixed_missing"),
+                    ))
+                }
+            },
             Some(b) => b,
             None => {
-                return Ok("No bitrate given".to_string());
+                let current_bitrate = {
+                    self.bela_state
+                        .read()
+                        .await
+                        .config
+                        .as_ref()
+                        .map(|config| config.max_br)
+                };
+
+                let bitrate = if let Some(current) = current_bitrate {
+                    current.to_string()
+                } else {
+                    self.templates.get("unknown")
+                };
+
+                return Ok(self
+                    .templates
+                    .render("bitrate_current", &[("bitrate", &bitrate)]));
             }
         };
 
         let bitrate = match bitrate.parse::<u32>() {
             Ok(b) => b,
             Err(_) => {
-                return Ok(format!("Invalid number {} given", bitrate));
+                return Err(CommandError::InvalidArgument(
+                    self.templates
+                        .render("invalid_number", &[("value", &bitrate)]),
+                ));
             }
         };
 
         if !(500..=12000).contains(&bitrate) {
-            let msg = format!(
-                "Invalid value: {}, use a value between 500 - 12000",
-                bitrate
+            let msg = self.templates.render(
+                "invalid_range",
+                &[("value", &bitrate), ("min", &500), ("max", &12000)],
             );
-            return Ok(msg);
+            return Err(CommandError::InvalidArgument(msg));
         }
 
         let bitrate = increment_by_step(bitrate as f64, 250.0) as u32;
@@ -248,14 +1550,18 @@ impl CommandHandler {
             }
         }
 
-        Ok(format!("Changed max bitrate to {} kbps", bitrate))
+        Ok(self
+            .templates
+            .render("bitrate_changed", &[("bitrate", &bitrate)]))
     }
 
-    pub async fn network(&self, name: Option<&str>) -> Result<String> {
+    pub async fn network(&self, name: Option<&str>) -> CommandResult {
         let name = match name {
             Some(b) => b.to_lowercase(),
             None => {
-                return Ok("No interface given".to_string());
+                return Err(CommandError::InvalidArgument(
+                    self.templates.get("interface_missing"),
+                ));
             }
         };
 
@@ -267,12 +1573,12 @@ impl CommandHandler {
         let netifs = match netifs {
             Some(n) => n,
             None => {
-                return Ok("Interfaces not available".to_string());
+                return Ok(self.templates.get("interfaces_unavailable"));
             }
         };
 
         if netifs.len() == 1 {
-            return Ok("You only have one connection!".to_string());
+            return Ok(self.templates.get("interface_only_one"));
         }
 
         let disabled_count = {
@@ -284,64 +1590,195 @@ impl CommandHandler {
                 }
             }
 
-            total
+            total
+        };
+
+        let interface = self.find_interface(&name, &netifs);
+
+        let (interface_name, interface) = match interface {
+            Some(i) => i,
+            None => {
+                return Ok(self.templates.get("interface_not_found"));
+            }
+        };
+
+        if netifs.len() - disabled_count == 1 && interface.enabled {
+            return Ok(self.templates.get("interface_last"));
+        }
+
+        let enabled = !interface.enabled;
+        let network = belabox::requests::Netif {
+            name: interface_name.to_owned(),
+            ip: interface.ip.to_owned(),
+            enabled,
+        };
+        self.belabox.netif(network).await?;
+
+        let key = if enabled {
+            "interface_enabled"
+        } else {
+            "interface_disabled"
+        };
+
+        Ok(self.templates.render(key, &[("interface", &name)]))
+    }
+
+    /// Enables or disables an interface and waits until the BELABOX reports
+    /// the new state.
+    pub async fn modem_toggle(&self, name: Option<&str>, state: Option<&str>) -> CommandResult {
+        let name = match name {
+            Some(n) => n.to_lowercase(),
+            None => {
+                return Err(CommandError::InvalidArgument(
+                    self.templates.get("interface_missing"),
+                ))
+            }
+        };
+
+        let enabled = match state.map(|s| s.to_lowercase()).as_deref() {
+            Some("on" | "enable") => true,
+            Some("off" | "disable") => false,
+            _ => {
+                return Err(CommandError::InvalidArgument(
+                    self.templates.get("interface_state_invalid"),
+                ))
+            }
+        };
+
+        let netifs = match self.bela_state.read().await.netif.to_owned() {
+            Some(n) => n,
+            None => return Ok(self.templates.get("interfaces_unavailable")),
+        };
+
+        let (interface_name, interface) = match self.find_interface(&name, &netifs) {
+            Some(i) => i,
+            None => return Ok(self.templates.get("interface_not_found")),
+        };
+        let display_name = self.interface_name(interface_name, interface);
+
+        if interface.enabled == enabled {
+            let key = if enabled {
+                "interface_already_enabled"
+            } else {
+                "interface_already_disabled"
+            };
+
+            return Ok(self.templates.render(key, &[("interface", &display_name)]));
+        }
+
+        let enabled_count = netifs.values().filter(|n| n.enabled).count();
+        if !enabled && enabled_count == 1 {
+            return Ok(self.templates.get("interface_last"));
+        }
+
+        let done = if enabled {
+            "interface_enabled"
+        } else {
+            "interface_disabled"
+        };
+
+        // Subscribe before sending so the new state can't be missed
+        let mut messages = self.belabox.message_stream()?;
+        let network = belabox::requests::Netif {
+            name: interface_name.to_owned(),
+            ip: interface.ip.to_owned(),
+            enabled,
+        };
+        self.belabox.netif(network).await?;
+
+        if self.belabox.dry_run {
+            return Ok(self.templates.render(done, &[("interface", &display_name)]));
+        }
+
+        let confirmation = async {
+            loop {
+                match messages.recv().await {
+                    Ok(belabox::Message::Netif(netif)) => {
+                        if netif.get(interface_name).map(|n| n.enabled) == Some(enabled) {
+                            return true;
+                        }
+                    }
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return false,
+                }
+            }
+        };
+
+        let key = match tokio::time::timeout(INTERFACE_TIMEOUT, confirmation).await {
+            Ok(true) => done,
+            Ok(false) | Err(_) => "interface_not_confirmed",
         };
 
-        let mut interface = netifs.get_key_value(&name);
+        Ok(self.templates.render(key, &[("interface", &display_name)]))
+    }
 
-        if interface.is_none() {
-            // get iterface name based on custom name
-            let mut possible_ip = None;
+    /// Reconnects a modem by disabling and enabling the interface.
+    pub async fn modem_restart(&self, channel: &str, name: Option<&str>) -> CommandResult {
+        let netifs = {
+            let read = self.bela_state.read().await;
+            read.netif.to_owned()
+        };
 
-            // Custom name based on interface
-            for (original, custom) in &self.custom_interface_name {
-                if name == custom.to_lowercase() {
-                    interface = netifs.get_key_value(original);
-                    possible_ip = Some(original);
-                    break;
-                }
+        let netifs = match netifs {
+            Some(n) => n,
+            None => {
+                return Ok(self.templates.get("interfaces_unavailable"));
             }
+        };
 
-            // Custom name based on ip
-            if interface.is_none() && possible_ip.is_some() {
-                let possible_ip = possible_ip.unwrap();
-
-                for (k, v) in &netifs {
-                    if &v.ip == possible_ip {
-                        interface = netifs.get_key_value(k);
-                        break;
-                    }
-                }
+        let name = match name {
+            Some(b) => b.to_lowercase(),
+            None => {
+                return Err(CommandError::InvalidArgument(self.templates.render(
+                    "modem_restart_missing",
+                    &[("interfaces", &self.interface_names(&netifs))],
+                )));
             }
-        }
+        };
 
-        let (interface_name, interface) = match interface {
+        let (interface_name, interface) = match self.find_interface(&name, &netifs) {
             Some(i) => i,
             None => {
-                return Ok("Interface not found".to_string());
+                return Ok(self.templates.render(
+                    "modem_restart_not_found",
+                    &[("interfaces", &self.interface_names(&netifs))],
+                ));
             }
         };
 
-        if netifs.len() - disabled_count == 1 && interface.enabled {
-            return Ok("Can't disable all networks".to_string());
+        if !interface.enabled {
+            return Ok(self
+                .templates
+                .render("modem_restart_disabled", &[("interface", &name)]));
         }
 
-        let enabled = !interface.enabled;
-        let network = belabox::requests::Netif {
+        let enabled_count = netifs.values().filter(|n| n.enabled).count();
+        if enabled_count == 1 {
+            return Ok(self.templates.get("modem_restart_last"));
+        }
+
+        let mut network = belabox::requests::Netif {
             name: interface_name.to_owned(),
             ip: interface.ip.to_owned(),
-            enabled,
+            enabled: false,
         };
+        self.belabox.netif(network.clone()).await?;
+        let msg = self
+            .templates
+            .render("modem_restarting", &[("interface", &name)]);
+        self.send(channel, msg).await;
+
+        tokio::time::sleep(Duration::from_secs(3)).await;
+
+        network.enabled = true;
         self.belabox.netif(network).await?;
 
-        Ok(format!(
-            "{} has been {}",
-            name,
-            if enabled { "enabled" } else { "disabled" }
-        ))
+        Ok(self
+            .templates
+            .render("modem_restarted", &[("interface", &name)]))
     }
 
-    pub async fn sensor(&self) -> Result<String> {
+    pub async fn sensor(&self) -> CommandResult {
         let sensors = {
             let read = self.bela_state.read().await;
             read.sensors.to_owned()
@@ -350,7 +1787,7 @@ impl CommandHandler {
         let sensors = match sensors {
             Some(s) => s,
             None => {
-                return Ok("Sensors not available".to_string());
+                return Ok(self.templates.get("sensors_unavailable"));
             }
         };
 
@@ -358,22 +1795,165 @@ impl CommandHandler {
             soc_voltage,
             soc_current,
             soc_temperature,
+            other,
+            ..
         } = sensors;
 
-        let mut response = format!("Temp: {}", soc_temperature);
+        let mut response = self
+            .templates
+            .render("sensor", &[("temperature", &soc_temperature)]);
 
         if let Some(voltage) = soc_voltage {
-            let _ = write!(response, ", Voltage: {}", voltage);
+            response = self.templates.render(
+                "sensor_voltage",
+                &[("sensor", &response), ("voltage", &voltage)],
+            );
         }
 
         if let Some(current) = soc_current {
-            let _ = write!(response, ", Amps: {}", current);
+            response = self.templates.render(
+                "sensor_current",
+                &[("sensor", &response), ("current", &current)],
+            );
+        }
+
+        // Sensors without a reading are left out
+        for (label, value) in other.iter().filter(|(_, v)| !v.trim().is_empty()) {
+            response = self.templates.render(
+                "sensor_other",
+                &[("sensor", &response), ("label", label), ("value", value)],
+            );
         }
 
         Ok(response)
     }
 
-    pub async fn latency(&self, latency: Option<&str>) -> Result<String> {
+    /// Shows the bitrate of the last minute as a sparkline.
+    pub async fn history(&self) -> CommandResult {
+        let history = self
+            .bela_state
+            .read()
+            .await
+            .bitrate_history
+            .iter()
+            .copied()
+            .collect::<Vec<u64>>();
+
+        let max = match history.iter().max() {
+            Some(m) => *m,
+            None => {
+                return Ok(self.templates.get("history_unavailable"));
+            }
+        };
+
+        Ok(self.templates.render(
+            "history",
+            &[
+                ("secs", &history.len()),
+                ("graph", &sparkline(&history)),
+                ("max", &max),
+            ],
+        ))
+    }
+
+    /// Shows the relay the BELABOX streams to.
+    pub async fn connection(&self) -> CommandResult {
+        let (is_streaming, config, relays) = {
+            let state = self.bela_state.read().await;
+
+            (
+                state.is_streaming,
+                state.config.to_owned(),
+                state.relays.to_owned(),
+            )
+        };
+
+        let config = match config {
+            Some(c) if is_streaming => c,
+            _ => {
+                return Ok(self.templates.get("connection_not_connected"));
+            }
+        };
+
+        // A relay server of BELABOX Cloud or a custom address
+        let relay = config
+            .relay_server
+            .as_ref()
+            .and_then(|id| relays.as_ref()?.servers.get(id))
+            .map(|server| server.name.to_owned())
+            .unwrap_or_else(|| config.srtla_addr.to_owned());
+        let address = format!("{}:{}", config.srtla_addr, config.srtla_port);
+
+        Ok(self
+            .templates
+            .render("connection", &[("relay", &relay), ("address", &address)]))
+    }
+
+    /// Shows the signal, network type and carrier of every modem.
+    pub async fn signal(&self) -> CommandResult {
+        let (modems, netifs) = {
+            let read = self.bela_state.read().await;
+            (
+                read.modems.to_owned(),
+                read.netif.to_owned().unwrap_or_default(),
+            )
+        };
+
+        let modems = match modems {
+            Some(m) if !m.is_empty() => m,
+            _ => {
+                return Ok(self.templates.get("modems_unavailable"));
+            }
+        };
+
+        let unknown = self.templates.get("unknown");
+
+        let mut response = modems
+            .values()
+            .map(|modem| {
+                let ifname = modem.ifname.as_deref().or(modem.name.as_deref());
+                let interface = match ifname {
+                    Some(ifname) => match netifs.get(ifname) {
+                        Some(netif) => self.interface_name(ifname, netif),
+                        None => self
+                            .custom_interface_name
+                            .read()
+                            .unwrap()
+                            .get(ifname)
+                            .map(|n| n.to_owned())
+                            .unwrap_or_else(|| ifname.to_owned()),
+                    },
+                    None => unknown.to_owned(),
+                };
+
+                let status = &modem.status;
+                let signal = status
+                    .signal
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| unknown.to_owned());
+
+                self.templates.render(
+                    "signal_modem",
+                    &[
+                        ("interface", &interface),
+                        (
+                            "network_type",
+                            &status.network_type.as_deref().unwrap_or(&unknown),
+                        ),
+                        ("signal", &signal),
+                        ("carrier", &status.network.as_deref().unwrap_or(&unknown)),
+                    ],
+                )
+            })
+            .collect::<Vec<String>>();
+
+        // Sort modems because they like to move around
+        response.sort();
+
+        Ok(response.join(", "))
+    }
+
+    pub async fn latency(&self, channel: &str, latency: Option<&str>) -> CommandResult {
         let latency = match latency {
             Some(b) => b,
             None => {
@@ -389,23 +1969,31 @@ impl CommandHandler {
                 let latency = if let Some(current) = current_latency {
                     current.to_string()
                 } else {
-                    "unknown".to_string()
+                    self.templates.get("unknown")
                 };
 
-                return Ok(format!("Current SRT latency is {} ms", latency));
+                return Ok(self
+                    .templates
+                    .render("latency_current", &[("latency", &latency)]));
             }
         };
 
         let latency = match latency.parse::<u64>() {
             Ok(l) => l,
             Err(_) => {
-                return Ok(format!("Invalid number {} given", latency));
+                return Err(CommandError::InvalidArgument(
+                    self.templates
+                        .render("invalid_number", &[("value", &latency)]),
+                ));
             }
         };
 
         if !(100..=4000).contains(&latency) {
-            let msg = format!("Invalid value: {}, use a value between 100 - 4000", latency);
-            return Ok(msg);
+            let msg = self.templates.render(
+                "invalid_range",
+                &[("value", &latency), ("min", &100), ("max", &4000)],
+            );
+            return Err(CommandError::InvalidArgument(msg));
         }
 
         let latency = increment_by_step(latency as f64, 100.0);
@@ -413,7 +2001,8 @@ impl CommandHandler {
 
         if is_streaming {
             let _ = self.stop().await?;
-            self.send("Restarting the stream".to_string()).await;
+            self.send(channel, self.templates.get("stream_restarting"))
+                .await;
             tokio::time::sleep(tokio::time::Duration::from_secs(5)).await
         }
 
@@ -429,10 +2018,92 @@ impl CommandHandler {
             let _ = self.start().await?;
         }
 
-        Ok(format!("Changed SRT latency to {} ms", latency))
+        Ok(self
+            .templates
+            .render("latency_changed", &[("latency", &latency)]))
+    }
+
+    /// Lists the outputs, or switches to the one with the name. A running
+    /// stream is restarted on the new output.
+    pub async fn output(&self, channel: &str, name: Option<&str>) -> CommandResult {
+        if self.outputs.is_empty() {
+            return Ok(self.templates.get("outputs_unavailable"));
+        }
+
+        let names = self
+            .outputs
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<&str>>()
+            .join(", ");
+
+        let name = match name {
+            Some(n) => n,
+            None => {
+                let current = {
+                    let lock = self.bela_state.read().await;
+                    lock.config.as_ref().and_then(|config| {
+                        self.outputs
+                            .iter()
+                            .find(|(_, output)| is_current_output(output, config))
+                            .map(|(name, _)| name.to_owned())
+                    })
+                };
+                let current = current.unwrap_or_else(|| self.templates.get("unknown"));
+
+                return Ok(self
+                    .templates
+                    .render("outputs", &[("outputs", &names), ("current", &current)]));
+            }
+        };
+
+        let (name, output) = match self
+            .outputs
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        {
+            Some(o) => o,
+            None => {
+                return Ok(self.templates.render(
+                    "output_not_found",
+                    &[("output", &name), ("outputs", &names)],
+                ));
+            }
+        };
+
+        let is_streaming = {
+            let lock = self.bela_state.read().await;
+
+            if lock.config.is_none() {
+                return Ok(self.templates.get("start_error"));
+            }
+
+            lock.is_streaming
+        };
+
+        if is_streaming {
+            let _ = self.stop().await?;
+            self.send(channel, self.templates.get("stream_restarting"))
+                .await;
+            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await
+        }
+
+        {
+            let mut lock = self.bela_state.write().await;
+
+            if let Some(config) = &mut lock.config {
+                apply_output(output, config);
+            }
+        }
+
+        if is_streaming {
+            let _ = self.start().await?;
+        }
+
+        Ok(self.templates.render("output_changed", &[("output", name)]))
     }
 
-    pub async fn audio_delay(&self, delay: Option<&str>) -> Result<String> {
+    pub async fn audio_delay(&self, channel: &str, delay: Option<&str>) -> CommandResult {
         let delay = match delay {
             Some(b) => b,
             None => {
@@ -448,23 +2119,31 @@ impl CommandHandler {
                 let delay = if let Some(current) = current_delay {
                     current.to_string()
                 } else {
-                    "unknown".to_string()
+                    self.templates.get("unknown")
                 };
 
-                return Ok(format!("Current audio delay is {} ms", delay));
+                return Ok(self
+                    .templates
+                    .render("audio_delay_current", &[("delay", &delay)]));
             }
         };
 
         let delay = match delay.parse::<i32>() {
             Ok(l) => l,
             Err(_) => {
-                return Ok(format!("Invalid number {} given", delay));
+                return Err(CommandError::InvalidArgument(
+                    self.templates
+                        .render("invalid_number", &[("value", &delay)]),
+                ));
             }
         };
 
         if delay.abs() > 2000 {
-            let msg = format!("Invalid value: {}, use a value between -2000 - 2000", delay);
-            return Ok(msg);
+            let msg = self.templates.render(
+                "invalid_range",
+                &[("value", &delay), ("min", &-2000), ("max", &2000)],
+            );
+            return Err(CommandError::InvalidArgument(msg));
         }
 
         let delay = increment_by_step(delay, 20.0);
@@ -472,7 +2151,8 @@ impl CommandHandler {
 
         if is_streaming {
             let _ = self.stop().await?;
-            self.send("Restarting the stream".to_string()).await;
+            self.send(channel, self.templates.get("stream_restarting"))
+                .await;
             tokio::time::sleep(tokio::time::Duration::from_secs(5)).await
         }
 
@@ -488,17 +2168,19 @@ impl CommandHandler {
             let _ = self.start().await?;
         }
 
-        Ok(format!("Changed audio delay to {} ms", delay))
+        Ok(self
+            .templates
+            .render("audio_delay_changed", &[("delay", &delay)]))
     }
 
-    pub(crate) async fn pipeline<'a, I>(&self, args: I) -> Result<String>
+    pub async fn pipeline<'a, I>(&self, channel: &str, args: I) -> CommandResult
     where
         I: IntoIterator<Item = &'a str>,
     {
         let args = args.into_iter();
         let query = args.collect::<Vec<&str>>().join(" ");
 
-        let (is_streaming, pipelines) = {
+        let (is_streaming, mut pipelines) = {
             let state = self.bela_state.read().await;
             let current_pipeline = state.config.as_ref().map(|config| &config.pipeline);
             let mut pipelines = Vec::new();
@@ -517,42 +2199,61 @@ impl CommandHandler {
                     .iter()
                     .filter(|(_, v)| v.name.contains(current))
                     .map(|(k, v)| (k.to_string(), v.name.split('/').nth(1).unwrap().to_owned()))
-                    .collect();
+                    .collect::<Vec<(String, String)>>();
             };
 
             (state.is_streaming, pipelines)
         };
 
-        if is_streaming {
-            let _ = self.stop().await?;
-            self.send("Restarting the stream".to_string()).await;
-            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await
+        if pipelines.is_empty() {
+            return Ok(self.templates.get("pipelines_unavailable"));
         }
 
-        // find pipeline
-        let found_pipeline = pipelines
-            .iter()
-            .map(|(h, p)| {
-                let pl = p.to_lowercase().replace('_', " ");
-                ((h, p), strsim::sorensen_dice(&query, &pl))
-            })
-            //     .collect::<Vec<(f64, (String, String))>>();
-            .min_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        // Sort pipelines so the numbers don't change between calls
+        pipelines.sort_by(|a, b| a.1.cmp(&b.1));
+
+        if query.is_empty() {
+            let list = pipelines
+                .iter()
+                .enumerate()
+                .map(|(i, (_, name))| format!("{}: {}", i + 1, name))
+                .collect::<Vec<String>>()
+                .join(", ");
+
+            return Ok(self.templates.render("pipelines", &[("pipelines", &list)]));
+        }
+
+        // find pipeline by number or by name
+        let found_pipeline = match query.parse::<usize>() {
+            Ok(n) => n.checked_sub(1).and_then(|i| pipelines.get(i)),
+            Err(_) => pipelines
+                .iter()
+                .map(|p| {
+                    let pl = p.1.to_lowercase().replace('_', " ");
+                    (p, strsim::sorensen_dice(&query, &pl))
+                })
+                .min_by(|a, b| b.1.partial_cmp(&a.1).unwrap())
+                .filter(|(_, score)| *score > 0.0)
+                .map(|(p, _)| p),
+        };
 
         let found_pipeline = match found_pipeline {
-            Some(p) => p,
-            None => return Ok("Pipeline not found".to_string()),
+            Some(p) => p.to_owned(),
+            None => return Ok(self.templates.get("pipeline_not_found")),
         };
 
-        if found_pipeline.1 == 0.0 {
-            return Ok("Pipeline not found".to_string());
+        if is_streaming {
+            let _ = self.stop().await?;
+            self.send(channel, self.templates.get("stream_restarting"))
+                .await;
+            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await
         }
 
         // change pipeline
         {
             let mut state = self.bela_state.write().await;
-            if let Some(mut config) = state.config.as_mut() {
-                config.pipeline = found_pipeline.0 .0.to_owned();
+            if let Some(config) = state.config.as_mut() {
+                config.pipeline = found_pipeline.0.to_owned();
             }
         }
 
@@ -560,10 +2261,12 @@ impl CommandHandler {
             let _ = self.start().await?;
         }
 
-        Ok(format!("Changed pipeline to {}", found_pipeline.0 .1))
+        Ok(self
+            .templates
+            .render("pipeline_changed", &[("pipeline", &found_pipeline.1)]))
     }
 
-    pub(crate) async fn audio_src<'a, I>(&self, args: I) -> Result<String>
+    pub async fn audio_src<'a, I>(&self, channel: &str, args: I) -> CommandResult
     where
         I: IntoIterator<Item = &'a str>,
     {
@@ -577,37 +2280,29 @@ impl CommandHandler {
             (state.is_streaming, asrcs)
         };
 
-        if is_streaming {
-            let _ = self.stop().await?;
-            self.send("Restarting the stream".to_string()).await;
-            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await
-        }
-
+        // Validate before stopping the stream
         let asrcs = match asrcs {
             Some(a) => a,
-            None => return Ok("No audio sources found".to_string()),
+            None => return Ok(self.templates.get("audio_sources_unavailable")),
         };
 
-        // find audio src
-        let found_asrcs = asrcs
-            .iter()
-            .map(|asrc| (asrc, strsim::sorensen_dice(&query, &asrc.to_lowercase())))
-            .min_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-
-        let found_asrcs = match found_asrcs {
-            Some(p) => p,
-            None => return Ok("Audio source not found".to_string()),
+        let found_asrc = match find_audio_source(&query, &asrcs) {
+            Some(a) => a,
+            None => return Ok(self.templates.get("audio_source_not_found")),
         };
 
-        if found_asrcs.1 == 0.0 {
-            return Ok("Audio source not found".to_string());
+        if is_streaming {
+            let _ = self.stop().await?;
+            self.send(channel, self.templates.get("stream_restarting"))
+                .await;
+            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await
         }
 
         // change audio src
         {
             let mut state = self.bela_state.write().await;
-            if let Some(mut config) = state.config.as_mut() {
-                config.asrc = found_asrcs.0.to_owned();
+            if let Some(config) = state.config.as_mut() {
+                config.asrc = found_asrc.to_owned();
             }
         }
 
@@ -615,8 +2310,184 @@ impl CommandHandler {
             let _ = self.start().await?;
         }
 
-        Ok(format!("Changed audio to {}", found_asrcs.0))
+        Ok(self
+            .templates
+            .render("audio_source_changed", &[("source", found_asrc)]))
+    }
+
+    /// Shows the audio source, or changes it by name like `audio_src`.
+    pub async fn audio<'a, I>(&self, channel: &str, args: I) -> CommandResult
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut args = args.into_iter().peekable();
+
+        if args.peek().is_some() {
+            return self.audio_src(channel, args).await;
+        }
+
+        let source = match &self.bela_state.read().await.config {
+            Some(c) => c.asrc.to_owned(),
+            None => self.templates.get("unknown"),
+        };
+
+        Ok(self.templates.render("audio", &[("source", &source)]))
+    }
+}
+
+/// Finds the audio source by name, the exact name wins over similar names.
+fn find_audio_source<'a>(query: &str, asrcs: &'a [String]) -> Option<&'a String> {
+    let query = query.to_lowercase();
+
+    if let Some(exact) = asrcs.iter().find(|a| a.to_lowercase() == query) {
+        return Some(exact);
+    }
+
+    asrcs
+        .iter()
+        .map(|asrc| (asrc, strsim::sorensen_dice(&query, &asrc.to_lowercase())))
+        .min_by(|a, b| b.1.partial_cmp(&a.1).unwrap())
+        .filter(|(_, score)| *score > 0.0)
+        .map(|(asrc, _)| asrc)
+}
+
+/// Points the stream config at the output, a relay server takes priority over
+/// the SRTLA receiver on the BELABOX.
+fn apply_output(output: &config::Output, config: &mut belabox::messages::Config) {
+    if let Some(server) = &output.relay_server {
+        config.relay_server = Some(server.to_owned());
+
+        if let Some(account) = &output.relay_account {
+            config.relay_account = Some(account.to_owned());
+        }
+    } else {
+        config.relay_server = None;
+        config.relay_account = None;
+
+        if let Some(addr) = &output.srtla_addr {
+            config.srtla_addr = addr.to_owned();
+        }
+
+        if let Some(port) = output.srtla_port {
+            config.srtla_port = port.to_string();
+        }
+    }
+
+    if let Some(streamid) = &output.srt_streamid {
+        config.srt_streamid = streamid.to_owned();
+    }
+}
+
+/// Whether the stream config already uses the output.
+fn is_current_output(output: &config::Output, config: &belabox::messages::Config) -> bool {
+    let mut applied = config.to_owned();
+    apply_output(output, &mut applied);
+
+    applied == *config
+}
+
+/// The longest start the triggers share, empty without triggers.
+fn common_prefix<'a>(mut triggers: impl Iterator<Item = &'a str>) -> &'a str {
+    let first = match triggers.next() {
+        Some(f) => f,
+        None => return "",
+    };
+
+    triggers.fold(first, |prefix, trigger| {
+        let len = prefix
+            .char_indices()
+            .zip(trigger.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map(|((i, c), _)| i + c.len_utf8())
+            .unwrap_or(0);
+
+        &prefix[..len]
+    })
+}
+
+/// The trigger which is one typo away from `trigger`, the first one in
+/// alphabetical order when there are more.
+fn typo_of<'a>(trigger: &str, triggers: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    triggers
+        .filter(|t| strsim::levenshtein(trigger, t) == 1)
+        .min()
+}
+
+/// The error with all of its causes, for example `BELABOX error: websocket
+/// send error`.
+fn error_chain(e: &dyn std::error::Error) -> String {
+    let mut chain = e.to_string();
+    let mut source = e.source();
+
+    while let Some(e) = source {
+        chain.push_str(": ");
+        chain.push_str(&e.to_string());
+        source = e.source();
+    }
+
+    chain
+}
+
+/// Formats a duration like `1h 05m` or `3m 20s`.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, (secs / 60) % 60, secs % 60);
+
+    if hours > 0 {
+        format!("{}h {:02}m", hours, minutes)
+    } else {
+        format!("{}m {:02}s", minutes, seconds)
+    }
+}
+
+/// Seconds since the unix epoch of an instant in the past.
+fn to_unix(instant: Instant) -> u64 {
+    let time = SystemTime::now() - instant.elapsed();
+
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// The instant of a time in seconds since the unix epoch, `None` when it's
+/// too long ago to be represented.
+fn from_unix(secs: u64) -> Option<Instant> {
+    let time = UNIX_EPOCH + Duration::from_secs(secs);
+    let elapsed = SystemTime::now().duration_since(time).unwrap_or_default();
+
+    Instant::now().checked_sub(elapsed)
+}
+
+/// Draws the values with block characters, scaled from zero to the highest
+/// value.
+fn sparkline(values: &[u64]) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let max = values.iter().copied().max().unwrap_or(0).max(1);
+    let top = (BLOCKS.len() - 1) as u64;
+
+    values
+        .iter()
+        .map(|v| BLOCKS[((v * top + max / 2) / max) as usize])
+        .collect()
+}
+
+/// Takes the lowercased command from the message, a space after the prefix
+/// like `! bbs` is allowed. The arguments are left as they are.
+fn parse_trigger<'a, I>(words: &mut I) -> Option<String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut trigger = words.next()?.to_lowercase();
+
+    if trigger.chars().all(|c| c.is_ascii_punctuation()) {
+        if let Some(command) = words.next() {
+            trigger.push_str(&command.to_lowercase());
+        }
     }
+
+    Some(trigger)
 }
 
 fn increment_by_step<V, S>(value: V, step: S) -> f64
@@ -629,3 +2500,206 @@ where
 
     (value / step).round() * step
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::belabox::mock::MockBelabox;
+
+    async fn mock_device(mock: &MockBelabox) -> Device {
+        let (_, monitor) = watch::channel(config::Monitor::default());
+
+        Device {
+            name: None,
+            twitch: Arc::new(Twitch::offline(vec!["715209".to_string()])),
+            belabox: Arc::new(mock.connect().await),
+            bela_state: Arc::new(RwLock::new(BelaState::default())),
+            custom_interface_name: Default::default(),
+            outputs: BTreeMap::new(),
+            templates: Arc::new(Templates::new(BTreeMap::new())),
+            monitor,
+        }
+    }
+
+    #[tokio::test]
+    async fn bitrate_request() {
+        let mock = MockBelabox::start(Vec::new()).await.unwrap();
+        let device = mock_device(&mock).await;
+
+        device.bitrate(Some("6100"), None).await.unwrap();
+        assert_eq!(
+            mock.request().await,
+            Some(serde_json::json!({ "bitrate": { "max_br": 6000 } }))
+        );
+
+        assert!(device.bitrate(Some("100"), None).await.is_err());
+        assert_eq!(mock.request().await, None);
+    }
+
+    #[tokio::test]
+    async fn fixed_bitrate_request() {
+        let mock = MockBelabox::start(Vec::new()).await.unwrap();
+        let device = mock_device(&mock).await;
+
+        device.bitrate(Some("fixed"), Some("5000")).await.unwrap();
+        assert_eq!(
+            mock.request().await,
+            Some(serde_json::json!({ "bitrate": { "max_br": 5000 } }))
+        );
+
+        assert!(matches!(
+            device.bitrate(Some("fixed"), None).await,
+            Err(CommandError::InvalidArgument(_))
+        ));
+        assert!(device.bitrate(Some("fixed"), Some("20000")).await.is_err());
+        assert_eq!(mock.request().await, None);
+    }
+
+    #[tokio::test]
+    async fn restart_stops_the_stream() {
+        let mock = MockBelabox::start(Vec::new()).await.unwrap();
+        let device = mock_device(&mock).await;
+        device.bela_state.write().await.is_streaming = true;
+
+        device.restart("715209").await.unwrap();
+        assert_eq!(mock.request().await, Some(serde_json::json!({ "stop": 0 })));
+        assert_eq!(
+            mock.request().await,
+            Some(serde_json::json!({ "command": "reboot" }))
+        );
+        assert!(matches!(
+            device.restart("715209").await,
+            Err(CommandError::Failed(Error::Belabox(
+                BelaboxError::AlreadyRestarting
+            )))
+        ));
+    }
+
+    #[tokio::test]
+    async fn stop_idle() {
+        let mock = MockBelabox::start(Vec::new()).await.unwrap();
+        let device = mock_device(&mock).await;
+        device.bela_state.write().await.is_streaming = true;
+
+        let reply = device.stop_mode(Some("idle")).await.unwrap();
+        assert_eq!(reply, device.templates.get("stop_idle"));
+        assert_eq!(mock.request().await, Some(serde_json::json!({ "stop": 0 })));
+
+        assert!(matches!(
+            device.stop_mode(Some("sleep")).await,
+            Err(CommandError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn sparkline_scale() {
+        assert_eq!(sparkline(&[0, 1000, 2000, 4000]), "▁▃▅█");
+        assert_eq!(sparkline(&[0, 0]), "▁▁");
+        assert_eq!(sparkline(&[]), "");
+    }
+
+    #[test]
+    fn trigger() {
+        let mut words = "!BBS".split_whitespace();
+        assert_eq!(parse_trigger(&mut words).as_deref(), Some("!bbs"));
+
+        let mut words = "  ! bbp  Cam Link ".split_whitespace();
+        assert_eq!(parse_trigger(&mut words).as_deref(), Some("!bbp"));
+        assert_eq!(words.collect::<Vec<_>>(), vec!["Cam", "Link"]);
+
+        assert_eq!(parse_trigger(&mut "".split_whitespace()), None);
+    }
+
+    #[test]
+    fn trigger_typo() {
+        let triggers = ["!bbstart", "!bbs", "!bbsa", "!bbstop"];
+
+        assert_eq!(typo_of("!bstart", triggers.into_iter()), Some("!bbstart"));
+        assert_eq!(typo_of("!bbstrt", triggers.into_iter()), Some("!bbstart"));
+        // Both are one typo away from `!bbsb`
+        assert_eq!(typo_of("!bbsb", triggers.into_iter()), Some("!bbs"));
+        assert_eq!(typo_of("!bbstrat", triggers.into_iter()), None);
+    }
+
+    #[test]
+    fn trigger_prefix() {
+        assert_eq!(
+            common_prefix(["!bbs", "!bbstart", "!bbl"].into_iter()),
+            "!bb"
+        );
+        assert_eq!(common_prefix(["!bbs", "~go"].into_iter()), "");
+        assert_eq!(common_prefix(std::iter::empty()), "");
+    }
+
+    #[test]
+    fn switch_output() {
+        let mut config: belabox::messages::Config = serde_json::from_value(serde_json::json!({
+            "password_hash": "", "remote_key": "", "max_br": 5000, "delay": 0,
+            "pipeline": "", "srt_latency": 2000, "srt_streamid": "live",
+            "srtla_addr": "", "srtla_port": "", "bitrate_overlay": false,
+            "asrc": "", "acodec": "", "relay_server": "1"
+        }))
+        .unwrap();
+
+        let backup = config::Output {
+            srtla_addr: Some("backup.example.com".to_string()),
+            srtla_port: Some(5000),
+            ..Default::default()
+        };
+        assert!(!is_current_output(&backup, &config));
+
+        apply_output(&backup, &mut config);
+        assert!(is_current_output(&backup, &config));
+        assert_eq!(config.relay_server, None);
+        assert_eq!(config.srtla_port, "5000");
+        assert_eq!(config.srt_streamid, "live");
+    }
+
+    #[test]
+    fn belabox_errors() {
+        assert!(matches!(
+            CommandError::from(BelaboxError::Disconnected),
+            CommandError::Disconnected
+        ));
+
+        let e = Error::Belabox(BelaboxError::AlreadyRestarting);
+        assert_eq!(error_chain(&e), "BELABOX error: Already restarting");
+    }
+
+    #[test]
+    fn cooldowns_saved() {
+        let path =
+            std::env::temp_dir().join(format!("belabot-cooldowns-{}.json", std::process::id()));
+        let info = config::CommandInformation {
+            command: "!bbpo".to_string(),
+            enabled: true,
+            aliases: Vec::new(),
+            permission: Permission::Broadcaster,
+            cooldown_secs: Some(3600),
+            user_cooldown_secs: None,
+            reply_mode: ReplyMode::Public,
+            usage: None,
+            quiet_success: false,
+        };
+        let commands = HashMap::from([(BotCommand::Poweroff, info)]);
+
+        let mut cooldowns = Cooldowns::default();
+        let poweroff = &commands[&BotCommand::Poweroff];
+        assert!(!cooldowns.is_on_cooldown(&BotCommand::Poweroff, poweroff, "715209"));
+        cooldowns.record(&BotCommand::Poweroff, poweroff, "715209");
+        cooldowns.save(&path, &commands).unwrap();
+
+        let loaded = Cooldowns::load(&path);
+        let _ = std::fs::remove_file(&path);
+        assert!(loaded.is_on_cooldown(&BotCommand::Poweroff, poweroff, "715209"));
+    }
+
+    #[test]
+    fn permission_denied_once() {
+        let mut cooldowns = Cooldowns::default();
+
+        assert!(cooldowns.report_denied(&BotCommand::Start, "troll"));
+        assert!(!cooldowns.report_denied(&BotCommand::Start, "troll"));
+        assert!(cooldowns.report_denied(&BotCommand::Stop, "troll"));
+    }
+}
@@ -1,36 +1,334 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::{IsTerminal, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
+use chrono::{DateTime, Local, NaiveTime, Utc};
 use read_input::prelude::*;
-use serde::{Deserialize, Serialize};
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Schema, SchemaObject, StringValidation},
+    JsonSchema,
+};
+use serde::{
+    de::{value::StrDeserializer, DeserializeOwned},
+    Deserialize, Serialize,
+};
 use thiserror::Error;
-use tracing::error;
+use tracing::{error, info, warn};
+
+use crate::templates::DEFAULT_TEMPLATES;
 
 const CONFIG_FILE_NAME: &str = "config.json";
 
+/// Environment variables which override the secrets in the config file.
+pub const ENV_TWITCH_USERNAME: &str = "BELABOT_TWITCH_USERNAME";
+pub const ENV_TWITCH_OAUTH: &str = "BELABOT_TWITCH_OAUTH";
+pub const ENV_REMOTE_KEY: &str = "BELABOT_REMOTE_KEY";
+
+/// Secrets starting with this are read from the file at the path after it.
+pub const SECRET_FILE_PREFIX: &str = "file:";
+
+/// The current schema version of the config.
+pub const CONFIG_VERSION: u32 = 2;
+
+/// Put in front of the default commands when no prefix is configured.
+pub const DEFAULT_COMMAND_PREFIX: &str = "!";
+
+/// How often saving the config of the setup is tried.
+const SETUP_SAVE_ATTEMPTS: u32 = 4;
+
+/// The wait before trying to save the setup again, doubled after every try.
+const SETUP_SAVE_WAIT: Duration = Duration::from_millis(500);
+
+/// Config file names which are looked for, in order.
+pub const CONFIG_FILE_NAMES: [&str; 4] =
+    [CONFIG_FILE_NAME, "config.toml", "config.yaml", "config.yml"];
+
+/// The directory of the config when no path is given, for example
+/// `~/.config/belabot` or `%APPDATA%\belabot`.
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("belabot"))
+}
+
+/// Finds the config when no path is given, the config directory is checked
+/// before the working directory. New configs go to the config directory.
+pub fn default_path() -> PathBuf {
+    let dir = config_dir();
+    let dirs = dir.iter().map(|d| d.as_path()).chain([Path::new("")]);
+
+    for dir in dirs {
+        for name in CONFIG_FILE_NAMES {
+            let path = dir.join(name);
+
+            if path.exists() {
+                return path;
+            }
+        }
+    }
+
+    match dir {
+        Some(dir) => dir.join(CONFIG_FILE_NAME),
+        None => PathBuf::from(CONFIG_FILE_NAME),
+    }
+}
+
+/// Moves a config found in the working directory to the config directory.
+///
+/// Returns the path to use, which is the old path when moving failed.
+pub fn move_to_config_dir(path: PathBuf) -> PathBuf {
+    let dir = match config_dir() {
+        Some(d) => d,
+        None => return path,
+    };
+
+    if !path.is_relative() || !path.exists() {
+        return path;
+    }
+
+    let target = dir.join(&path);
+
+    let moved = std::fs::create_dir_all(&dir).and_then(|_| {
+        // Renaming fails across file systems
+        std::fs::rename(&path, &target)
+            .or_else(|_| std::fs::copy(&path, &target).and_then(|_| std::fs::remove_file(&path)))
+    });
+
+    match moved {
+        Ok(()) => {
+            info!(from = %path.display(), to = %target.display(), "moved the config");
+            target
+        }
+        Err(e) => {
+            warn!(?e, path = %path.display(), "failed to move the config");
+            path
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum ConfigError {
     #[error("IO Error")]
     Io(#[from] std::io::Error),
     #[error("Json error: {0}")]
     Json(#[from] serde_json::error::Error),
+    #[error("Toml error: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("Toml serialize error: {0}")]
+    TomlSerialize(#[from] toml::ser::Error),
+    #[error("Yaml error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("No key found in the BELABOX Cloud remote URL")]
+    InvalidRemoteKey,
+    #[error("The interface {interface} has more than one custom name: {names}")]
+    AmbiguousInterfaceName { interface: String, names: String },
+    #[error("Invalid time range {0}, use for example 23:00-08:00")]
+    InvalidTimeRange(String),
+    #[error("The output {0} needs a relay_server or a srtla_addr and srtla_port")]
+    InvalidOutput(String),
+    #[error("The trigger {trigger} is used by more than one command: {commands}")]
+    DuplicateTrigger { trigger: String, commands: String },
+    #[error("{0} is not set")]
+    MissingSetting(String),
+    #[error("{0} is not a remote key, use only the part after ?key= of the remote URL")]
+    MalformedRemoteKey(String),
+    #[error("The interface {0} has an empty custom name")]
+    EmptyInterfaceName(String),
+    #[error("The custom name {name} is used by more than one interface: {interfaces}")]
+    SharedInterfaceName { name: String, interfaces: String },
+    #[error("The group {group} of {used_by} is not in twitch.groups")]
+    UnknownGroup { group: String, used_by: String },
+    #[error("Failed to read the secret file {path}")]
+    SecretFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+/// The format of a config file, based on its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// Detects the format from the file extension, defaults to JSON.
+    pub fn from_path<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let extension = path
+            .as_ref()
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+
+        match extension.as_deref() {
+            Some("toml") => Self::Toml,
+            Some("yaml" | "yml") => Self::Yaml,
+            _ => Self::Json,
+        }
+    }
+
+    /// Unknown settings, for example from a newer version of belabot, are
+    /// ignored with a warning.
+    pub fn deserialize(&self, contents: &str) -> Result<Settings, ConfigError> {
+        let mut ignored = Vec::new();
+        let mut unknown = |path: serde_ignored::Path| ignored.push(path.to_string());
+
+        let settings = match self {
+            Self::Json => {
+                let mut deserializer = serde_json::Deserializer::from_str(contents);
+                let settings = serde_ignored::deserialize(&mut deserializer, &mut unknown)?;
+                deserializer.end()?;
+                settings
+            }
+            Self::Toml => {
+                serde_ignored::deserialize(toml::Deserializer::new(contents), &mut unknown)?
+            }
+            Self::Yaml => serde_ignored::deserialize(
+                serde_yaml::Deserializer::from_str(contents),
+                &mut unknown,
+            )?,
+        };
+
+        if !ignored.is_empty() {
+            warn!(
+                settings = ignored.join(", "),
+                "Ignoring unknown settings, they are removed when the config is saved"
+            );
+        }
+
+        Ok(settings)
+    }
+
+    fn deserialize_as<T>(&self, contents: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let settings = match self {
+            Self::Json => serde_json::from_str(contents)?,
+            Self::Toml => toml::from_str(contents)?,
+            Self::Yaml => serde_yaml::from_str(contents)?,
+        };
+
+        Ok(settings)
+    }
+
+    pub fn serialize(&self, settings: &Settings) -> Result<String, ConfigError> {
+        let contents = match self {
+            Self::Json => serde_json::to_string_pretty(settings)?,
+            Self::Toml => toml::to_string_pretty(settings)?,
+            Self::Yaml => serde_yaml::to_string(settings)?,
+        };
+
+        Ok(contents)
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
 pub struct Settings {
+    /// The schema version of the config, configs without it are version 0
+    #[serde(default)]
+    pub version: u32,
+    /// Log the requests commands would send instead of sending them
+    #[serde(default)]
+    pub dry_run: bool,
+    /// `Version` checks GitHub for a newer release
+    #[serde(default)]
+    pub check_updates: bool,
+    /// Put in front of the default commands instead of `!`, commands which
+    /// are already in the config keep their trigger
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command_prefix: Option<String>,
     pub belabox: Belabox,
     pub twitch: Twitch,
+    #[serde(deserialize_with = "known_commands")]
+    #[schemars(schema_with = "commands_schema")]
     pub commands: HashMap<BotCommand, CommandInformation>,
+    #[serde(default)]
+    pub notifications: Notifications,
+    #[serde(default)]
+    pub status_server: StatusServer,
+    #[serde(default)]
+    pub metrics: Metrics,
+    #[serde(default)]
+    pub obs: Obs,
+    #[serde(default)]
+    pub safety: Safety,
+    #[serde(default)]
+    pub unknown_commands: UnknownCommands,
+    #[serde(default)]
+    pub logging: Logging,
+    #[serde(default)]
+    pub usage: UsageStats,
+    /// Chat messages of the bot, see `templates::DEFAULT_TEMPLATES`
+    #[serde(default)]
+    pub messages: BTreeMap<String, String>,
+    /// The path the config was loaded from or saved to.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
 #[serde(default)]
 pub struct Belabox {
     pub remote_key: String,
+    /// Websocket URL of a self-hosted BELABOX Cloud, the public one is used
+    /// when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cloud_url: Option<String>,
     pub custom_interface_name: HashMap<String, String>,
+    /// Where the stream can be sent to with `Output`, by name
+    pub outputs: BTreeMap<String, Output>,
     pub monitor: Monitor,
+    /// Additional devices which can be targeted by name in commands
+    pub devices: BTreeMap<String, Device>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Device {
+    pub remote_key: String,
+    /// Defaults to the `cloud_url` of the primary device
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cloud_url: Option<String>,
+    pub custom_interface_name: HashMap<String, String>,
+    pub outputs: BTreeMap<String, Output>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// A destination of the stream, either a BELABOX Cloud relay or a SRTLA
+/// receiver.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Output {
+    /// The id of the relay server, takes priority over `srtla_addr`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relay_server: Option<String>,
+    /// The id of the relay account, the current one is kept when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relay_account: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub srtla_addr: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub srtla_port: Option<u16>,
+    /// The current stream id is kept when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub srt_streamid: Option<String>,
+}
+
+impl Output {
+    fn is_valid(&self) -> bool {
+        self.relay_server.is_some() || (self.srtla_addr.is_some() && self.srtla_port.is_some())
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 #[serde(default)]
 pub struct Monitor {
     pub modems: bool,
@@ -38,6 +336,33 @@ pub struct Monitor {
     pub ups: bool,
     pub ups_plugged_in: f64,
     pub notification_timeout: u64,
+    /// Identical monitor messages within this many seconds are collapsed into
+    /// one, 0 disables it
+    pub throttle_secs: u64,
+    /// Warn in chat when the bitrate is low
+    pub bitrate: bool,
+    /// Total bitrate in kbps below which the bitrate is low
+    pub bitrate_warn_kbps: u64,
+    /// Seconds the bitrate has to be low before warning
+    pub bitrate_warn_secs: u64,
+    /// Warn when the SoC temperature is above this, disabled when not set
+    pub temperature_warn_celsius: Option<f64>,
+    /// Seconds before warning about the temperature again
+    pub temperature_warn_cooldown_secs: u64,
+    /// Seconds without any data from the BELABOX before commands treat it
+    /// as offline
+    pub stale_secs: u64,
+    /// Seconds after `Restart` before warning that the stream didn't come
+    /// back
+    pub restart_timeout_secs: u64,
+    /// Restart the stream when the bitrate stays at 0 kbps this many seconds
+    /// while streaming, disabled when not set
+    pub auto_restart_on_stall_secs: Option<u64>,
+    /// Automatic restarts before giving up, counted again after 10 minutes
+    /// without one
+    pub auto_restart_max_attempts: u32,
+    /// Times when notifications are only logged
+    pub quiet_hours: QuietHours,
 }
 
 impl Default for Monitor {
@@ -48,209 +373,1430 @@ impl Default for Monitor {
             ups: false,
             ups_plugged_in: 5.1,
             notification_timeout: 30,
+            throttle_secs: 60,
+            bitrate: false,
+            bitrate_warn_kbps: 500,
+            bitrate_warn_secs: 5,
+            temperature_warn_celsius: None,
+            temperature_warn_cooldown_secs: 300,
+            stale_secs: 30,
+            restart_timeout_secs: 120,
+            auto_restart_on_stall_secs: None,
+            auto_restart_max_attempts: 3,
+            quiet_hours: QuietHours::default(),
+        }
+    }
+}
+
+/// Times when the monitor logs notifications instead of sending them to chat
+/// and Discord.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+#[serde(default)]
+pub struct QuietHours {
+    /// For example `Europe/Amsterdam`, the local timezone when not set
+    #[schemars(with = "Option<String>")]
+    pub timezone: Option<chrono_tz::Tz>,
+    pub ranges: Vec<TimeRange>,
+}
+
+impl QuietHours {
+    /// Whether `now` is in one of the ranges, in the configured timezone so
+    /// daylight saving time moves the ranges with the clock.
+    pub fn is_quiet(&self, now: DateTime<Utc>) -> bool {
+        if self.ranges.is_empty() {
+            return false;
+        }
+
+        let time = match self.timezone {
+            Some(tz) => now.with_timezone(&tz).time(),
+            None => now.with_timezone(&Local).time(),
+        };
+
+        self.ranges.iter().any(|r| r.contains(time))
+    }
+}
+
+/// A time range like `23:00-08:00`, it can go past midnight.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeRange {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeRange {
+    /// The start is part of the range, the end isn't.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl TryFrom<String> for TimeRange {
+    type Error = ConfigError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let parse = |t: &str| NaiveTime::parse_from_str(t.trim(), "%H:%M").ok();
+
+        let range = value
+            .split_once('-')
+            .and_then(|(start, end)| Some((parse(start)?, parse(end)?)));
+
+        match range {
+            Some((start, end)) => Ok(Self { start, end }),
+            None => Err(ConfigError::InvalidTimeRange(value)),
+        }
+    }
+}
+
+impl JsonSchema for TimeRange {
+    fn schema_name() -> String {
+        "TimeRange".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some(r"^\s*\d{1,2}:\d{2}\s*-\s*\d{1,2}:\d{2}\s*$".to_string()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+impl From<TimeRange> for String {
+    fn from(range: TimeRange) -> Self {
+        format!(
+            "{}-{}",
+            range.start.format("%H:%M"),
+            range.end.format("%H:%M")
+        )
+    }
+}
+
+/// Counts how often commands are used.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(default)]
+pub struct UsageStats {
+    /// The usage is saved to this file, only kept in memory when not set
+    pub file: Option<PathBuf>,
+    /// Seconds between saving the usage
+    pub save_interval_secs: u64,
+}
+
+impl Default for UsageStats {
+    fn default() -> Self {
+        Self {
+            file: Some(PathBuf::from("usage.json")),
+            save_interval_secs: 300,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable
+    #[default]
+    Pretty,
+    /// One JSON object per line
+    Json,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Logging {
+    pub format: LogFormat,
+    /// Filter such as `belabot=debug`, `RUST_LOG` takes priority over it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<String>,
+    /// Seconds between logs of the connection states, off when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heartbeat_secs: Option<u64>,
+}
+
+impl Logging {
+    /// Reads only the logging settings so logging can be set up before the
+    /// config is loaded, the defaults are used when that fails.
+    pub fn read<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        #[derive(Deserialize, Default)]
+        struct Partial {
+            #[serde(default)]
+            logging: Logging,
+        }
+
+        let path = path.as_ref();
+
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|file| {
+                ConfigFormat::from_path(path)
+                    .deserialize_as::<Partial>(&file)
+                    .ok()
+            })
+            .unwrap_or_default()
+            .logging
+    }
+}
+
+/// Protection against accidentally running destructive commands.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(default)]
+pub struct Safety {
+    /// Seconds to confirm `Poweroff` and `Restart`, 0 disables the confirmation
+    pub confirm_secs: u64,
+    /// Allow sending any message to the BELABOX with `Raw`
+    pub allow_raw: bool,
+    /// Only allow commands which show the status, see
+    /// [`BotCommand::is_read_only`]
+    pub read_only: bool,
+    /// The running cooldowns are saved to this file so they last across
+    /// restarts, only kept in memory when not set
+    pub cooldown_file: Option<PathBuf>,
+}
+
+impl Default for Safety {
+    fn default() -> Self {
+        Self {
+            confirm_secs: 15,
+            allow_raw: false,
+            read_only: false,
+            cooldown_file: Some(PathBuf::from("cooldowns.json")),
+        }
+    }
+}
+
+/// Read-only HTTP server with the current BELABOX status as JSON.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(default)]
+pub struct StatusServer {
+    pub enabled: bool,
+    /// The address to listen on, for example `127.0.0.1:8080`
+    pub address: SocketAddr,
+}
+
+impl Default for StatusServer {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: SocketAddr::from(([127, 0, 0, 1], 8080)),
+        }
+    }
+}
+
+/// HTTP server with Prometheus metrics at `/metrics`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(default)]
+pub struct Metrics {
+    pub enabled: bool,
+    /// The address to listen on, for example `127.0.0.1:9110`
+    pub address: SocketAddr,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: SocketAddr::from(([127, 0, 0, 1], 9110)),
+        }
+    }
+}
+
+/// Replies to messages which look like a command but don't match any, the bot
+/// ignores them by default.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(default)]
+pub struct UnknownCommands {
+    /// Reply with a hint to use `Help`
+    pub hint: bool,
+    /// Suggest the command one typo away, such as `!bbstart` for `!bstart`
+    pub suggest: bool,
+    /// Seconds between hints and suggestions in all channels
+    pub cooldown_secs: u64,
+}
+
+impl Default for UnknownCommands {
+    fn default() -> Self {
+        Self {
+            hint: false,
+            suggest: false,
+            cooldown_secs: 60,
+        }
+    }
+}
+
+/// Notifications sent outside of Twitch chat.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Notifications {
+    /// Sends stream events to this Discord webhook
+    pub discord_webhook_url: Option<String>,
+    pub targets: NotificationTargets,
+}
+
+/// Where a notification is sent.
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationTarget {
+    Chat,
+    Discord,
+    Log,
+}
+
+/// The targets of each event. Events without targets are sent to chat as
+/// the `monitor` settings say and to Discord when a webhook is set.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+#[serde(default)]
+pub struct NotificationTargets {
+    /// The stream started or stopped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<BTreeSet<NotificationTarget>>,
+    /// Modems connected or disconnected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modems: Option<BTreeSet<NotificationTarget>>,
+    /// The bitrate is low or recovered
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bitrate: Option<BTreeSet<NotificationTarget>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<BTreeSet<NotificationTarget>>,
+}
+
+/// Switches OBS scenes over OBS WebSocket when the bitrate is low.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(default)]
+pub struct Obs {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// The scene to switch to when the bitrate is low
+    pub low_bitrate_scene: String,
+    /// The scene to switch back to, the scene before switching when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub live_scene: Option<String>,
+}
+
+impl Default for Obs {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "127.0.0.1".to_string(),
+            port: 4455,
+            password: None,
+            low_bitrate_scene: "BRB".to_string(),
+            live_scene: None,
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
 pub struct Twitch {
     pub bot_username: String,
     pub bot_oauth: String,
-    pub channel: String,
+    /// The channels the bot joins, a single channel name is accepted as well
+    #[serde(alias = "channel", deserialize_with = "one_or_many")]
+    #[schemars(with = "OneOrMany")]
+    pub channels: Vec<String>,
     pub admins: Vec<String>,
+    /// Permissions of users which take priority over their roles
+    #[serde(default)]
+    pub user_overrides: HashMap<String, Permission>,
+    /// Named groups of users which can be used as `Group` permission
+    #[serde(default)]
+    pub groups: HashMap<String, Vec<String>>,
+    /// Put in front of every message of the bot, such as `[belabot]`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub reply_prefix: String,
+    /// Put after every message of the bot
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub reply_suffix: String,
+    /// Posted after joining a channel, the bot joins silently when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub join_message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh: Option<TwitchRefresh>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel_points: Option<ChannelPoints>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Deserializes a single string as a list with one item.
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(one) => vec![one],
+        OneOrMany::Many(many) => many,
+    })
+}
+
+/// Runs commands when viewers redeem channel point rewards, read with Twitch
+/// EventSub.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+pub struct ChannelPoints {
+    /// Oauth of the broadcaster with the `channel:read:redemptions` scope
+    pub oauth: String,
+    /// The command of each reward, by reward id
+    pub rewards: HashMap<String, Reward>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// A reward has its own permission instead of the one of its command, the
+/// arguments are still checked.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct Reward {
+    pub command: BotCommand,
+    /// `{input}` is replaced with the text the viewer entered
+    #[serde(default)]
+    pub arguments: String,
+    /// Who can redeem the reward, everyone by default
+    #[serde(default = "public")]
+    pub permission: Permission,
+    /// The lowest number the viewer can enter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<i64>,
+    /// The highest number the viewer can enter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<i64>,
+}
+
+fn public() -> Permission {
+    Permission::Public
+}
+
+/// Used to refresh the bot oauth when it expires.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+pub struct TwitchRefresh {
+    pub client_id: String,
+    pub client_secret: String,
+    pub refresh_token: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct CommandInformation {
     pub command: String,
+    /// Disabled commands are ignored
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    /// Other triggers for this command
+    #[serde(default)]
+    pub aliases: Vec<String>,
     pub permission: Permission,
+    /// Seconds before the command can be used again by anyone
+    #[serde(default)]
+    pub cooldown_secs: Option<u64>,
+    /// Seconds before the command can be used again by the same user
+    #[serde(default)]
+    pub user_cooldown_secs: Option<u64>,
+    /// Where the reply of the command is sent
+    #[serde(default, skip_serializing_if = "ReplyMode::is_public")]
+    pub reply_mode: ReplyMode,
+    /// Shown when an argument is wrong, `{command}` is replaced with the
+    /// trigger. Empty to only show the error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<String>,
+    /// No reply when the command worked, errors are still shown. Commands
+    /// which only show information always reply
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub quiet_success: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplyMode {
+    /// In the chat the command was used in
+    #[default]
+    Public,
+    /// As a whisper to the user of the command
+    Whisper,
+}
+
+impl ReplyMode {
+    fn is_public(&self) -> bool {
+        *self == ReplyMode::Public
+    }
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl CommandInformation {
+    /// Checks if the trigger is the command or one of its aliases.
+    pub fn is_trigger(&self, trigger: &str) -> bool {
+        self.command == trigger || self.aliases.iter().any(|a| a == trigger)
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Hash, PartialEq, Eq)]
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord,
+)]
 pub enum BotCommand {
+    Audio,
     AudioDelay,
     AudioSrc,
     Bitrate,
+    BotUptime,
+    Connection,
+    Help,
+    History,
+    Label,
     Latency,
+    ModemRestart,
+    ModemToggle,
     Network,
+    Output,
     Pipeline,
     Poweroff,
+    Raw,
+    ReadOnly,
+    Reload,
     Restart,
     Sensor,
+    Signal,
     Start,
     Stats,
     Stop,
+    Top,
+    Version,
+}
+
+impl BotCommand {
+    /// Whether only the broadcaster and admins can use the command, whatever
+    /// its permission says. These can do anything.
+    pub fn is_broadcaster_only(&self) -> bool {
+        matches!(
+            self,
+            BotCommand::Raw | BotCommand::ReadOnly | BotCommand::Reload
+        )
+    }
+
+    /// Whether the command can be used in read-only mode. These only show the
+    /// status, except for `ReadOnly` and `Reload` which manage the bot itself.
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            BotCommand::BotUptime
+                | BotCommand::Connection
+                | BotCommand::Help
+                | BotCommand::History
+                | BotCommand::ReadOnly
+                | BotCommand::Reload
+                | BotCommand::Sensor
+                | BotCommand::Signal
+                | BotCommand::Stats
+                | BotCommand::Top
+                | BotCommand::Version
+        )
+    }
+}
+
+/// Leaves out commands this version doesn't know instead of failing, for
+/// example after a downgrade.
+fn known_commands<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<BotCommand, CommandInformation>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let commands = HashMap::<String, CommandInformation>::deserialize(deserializer)?;
+    let mut known = HashMap::with_capacity(commands.len());
+
+    for (name, info) in commands {
+        let key = StrDeserializer::<serde::de::value::Error>::new(&name);
+
+        match BotCommand::deserialize(key) {
+            Ok(command) => {
+                known.insert(command, info);
+            }
+            Err(_) => warn!(
+                command = name,
+                "Ignoring an unknown command, it's removed when the config is saved"
+            ),
+        }
+    }
+
+    Ok(known)
+}
+
+/// The keys of `commands` can only be a [`BotCommand`].
+fn commands_schema(gen: &mut SchemaGenerator) -> Schema {
+    let mut schema = gen.subschema_for::<HashMap<String, CommandInformation>>();
+
+    if let Schema::Object(object) = &mut schema {
+        object.object().property_names = Some(Box::new(gen.subschema_for::<BotCommand>()));
+    }
+
+    schema
+}
+
+/// The JSON Schema of the config, for editors to validate and autocomplete it.
+pub fn json_schema() -> String {
+    let schema = schemars::schema_for!(Settings);
+
+    serde_json::to_string_pretty(&schema).expect("schema is valid JSON")
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub enum Permission {
     Broadcaster,
     Moderator,
     Vip,
+    Subscriber,
     Public,
+    /// Only the users in the group with this name
+    Group(String),
+}
+
+impl Permission {
+    /// The position in the hierarchy Broadcaster > Moderator > Vip >
+    /// Subscriber > Public, `None` for groups which are outside of it.
+    fn rank(&self) -> Option<u8> {
+        match self {
+            Permission::Public => Some(0),
+            Permission::Subscriber => Some(1),
+            Permission::Vip => Some(2),
+            Permission::Moderator => Some(3),
+            Permission::Broadcaster => Some(4),
+            Permission::Group(_) => None,
+        }
+    }
+
+    /// Whether a user with `role` may use a command which needs this
+    /// permission. Higher roles pass the lower ones, so a moderator may use
+    /// `Vip` commands. A group is passed by the broadcaster and a user with
+    /// that group as role, the members of a group are checked by the caller.
+    pub fn satisfies(&self, role: &Permission) -> bool {
+        match (self, role) {
+            (Permission::Public, _) | (Permission::Group(_), Permission::Broadcaster) => true,
+            (Permission::Group(needed), Permission::Group(has)) => needed == has,
+            _ => match (self.rank(), role.rank()) {
+                (Some(needed), Some(has)) => has >= needed,
+                _ => false,
+            },
+        }
+    }
 }
 
 impl Settings {
-    /// Loads the config
+    /// The prefix of the default commands.
+    pub fn command_prefix(&self) -> &str {
+        self.command_prefix
+            .as_deref()
+            .unwrap_or(DEFAULT_COMMAND_PREFIX)
+    }
+
+    /// Loads the config, the format is based on the file extension.
     pub fn load<P>(path: P) -> Result<Self, ConfigError>
     where
-        P: AsRef<std::path::Path>,
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let (mut config, dirty) = Self::parse(path)?;
+
+        // Only write the config back when something changed to keep the
+        // formatting of hand-edited files.
+        if dirty {
+            config.save(path)?;
+        }
+
+        config.apply_overrides(path)?;
+
+        Ok(config)
+    }
+
+    /// Loads the config like [`Settings::load`], but upgrades and new
+    /// defaults are not written back to the file.
+    pub fn read<P>(path: P) -> Result<Self, ConfigError>
+    where
+        P: AsRef<Path>,
     {
+        let path = path.as_ref();
+        let (mut config, _) = Self::parse(path)?;
+        config.apply_overrides(path)?;
+
+        Ok(config)
+    }
+
+    /// Reads and upgrades the config, returns `true` as well when it changed.
+    fn parse(path: &Path) -> Result<(Self, bool), ConfigError> {
+        let format = ConfigFormat::from_path(path);
         let file = std::fs::read_to_string(path)?;
-        let mut config = match serde_json::from_str::<Settings>(&file) {
+        let mut config = match format.deserialize(&file) {
             Ok(c) => c,
             Err(e) => {
                 error!(%e, "config error");
-                return Err(ConfigError::Json(e));
+                return Err(e);
             }
         };
 
+        // Upgrade configs written by older versions.
+        let mut dirty = migrate(&mut config);
+
         // Lowercase important settings such as the twitch channel name to
         // avoid issues.
-        lowercase_settings(&mut config);
+        dirty |= lowercase_settings(&mut config);
+
+        // Remove whitespace and duplicates from the custom interface names.
+        dirty |= normalize_interface_names(&mut config.belabox.custom_interface_name)?;
+        for device in config.belabox.devices.values_mut() {
+            dirty |= normalize_interface_names(&mut device.custom_interface_name)?;
+        }
+
+        let outputs = config.belabox.devices.values().flat_map(|d| &d.outputs);
+        for (name, output) in config.belabox.outputs.iter().chain(outputs) {
+            if !output.is_valid() {
+                return Err(ConfigError::InvalidOutput(name.to_owned()));
+            }
+        }
 
         // Insert chat commands in the config if they don't exist.
-        default_chat_commands(&mut config.commands);
+        let prefix = config.command_prefix().to_owned();
+        dirty |= default_chat_commands(&mut config.commands, &prefix);
+        check_duplicate_triggers(&config.commands)?;
 
-        std::fs::write(CONFIG_FILE_NAME, serde_json::to_string_pretty(&config)?)?;
+        // Insert chat messages in the config if they don't exist.
+        dirty |= default_messages(&mut config.messages);
 
-        Ok(config)
+        Ok((config, dirty))
     }
 
-    pub async fn ask_for_settings() -> Result<Self, ConfigError> {
-        println!("Please paste your BELABOX Cloud remote URL below");
+    /// Secret files and environment variables beat the config file. They are
+    /// applied after saving so the secrets never end up in the file.
+    fn apply_overrides(&mut self, path: &Path) -> Result<(), ConfigError> {
+        let mut overridden = resolve_secret_files(self)?;
+        overridden |= env_overrides(self);
 
-        let remote_key: String = input()
-            .msg("URL: ")
-            .add_err_test(
-                |u: &String| u.contains("?key="),
-                "No key found, please try again",
-            )
-            .get()
-            .split("?key=")
-            .nth(1)
-            .expect("No key found")
-            .to_string();
+        if overridden {
+            lowercase_settings(self);
+        }
 
-        let mut custom_interface_name = HashMap::new();
-        custom_interface_name.insert("eth0".to_string(), "eth0".to_string());
-        custom_interface_name.insert("usb0".to_string(), "usb0".to_string());
-        custom_interface_name.insert("wlan0".to_string(), "wlan0".to_string());
+        self.path = Some(path.to_path_buf());
 
-        println!("\nDo you want to receive automatic chat messages about:");
-
-        let is_y_or_n = |x: &String| x.to_lowercase() == "y" || x.to_lowercase() == "n";
-        let mut monitor = Monitor {
-            modems: input_to_bool(
-                input()
-                    .msg("The status of your modems (Y/n): ")
-                    .add_test(is_y_or_n)
-                    .err("Please enter y or n: ")
-                    .default("y".to_string())
-                    .get(),
-            ),
-            notifications: input_to_bool(
-                input()
-                    .msg("The belaUI notifications (Y/n): ")
-                    .add_test(is_y_or_n)
-                    .err("Please enter y or n: ")
-                    .default("y".to_string())
-                    .get(),
-            ),
-            ups: input_to_bool(
-                input()
-                    .msg("The status of your UPS (y/N): ")
-                    .add_test(is_y_or_n)
-                    .err("Please enter y or n: ")
-                    .default("n".to_string())
-                    .get(),
-            ),
-            ..Default::default()
-        };
+        Ok(())
+    }
+
+    /// Finds the problems which `load` lets through but which keep the bot
+    /// from working, such as missing credentials or unknown groups.
+    pub fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = Vec::new();
 
-        if monitor.ups {
-            monitor.ups_plugged_in = input()
-                .msg("UPS charging threshold (default 5.1 V): ")
-                .err("Please enter a number")
-                .default(5.1)
-                .get();
+        if let Err(e) = check_duplicate_triggers(&self.commands) {
+            errors.push(e);
         }
 
-        let belabox = Belabox {
-            remote_key,
-            custom_interface_name,
-            monitor,
-        };
+        let credentials = [
+            ("twitch.bot_username", &self.twitch.bot_username),
+            ("twitch.bot_oauth", &self.twitch.bot_oauth),
+        ];
 
-        println!("\nPlease enter your Twitch details below");
-        let mut twitch = Twitch {
-            bot_username: input().msg("Bot username: ").get(),
-            bot_oauth: input()
-                .msg("(You can generate an Oauth here: https://twitchapps.com/tmi/)\nBot oauth: ")
-                .get(),
-            channel: input().msg("Channel name: ").get(),
-            admins: Vec::new(),
-        };
+        for (setting, value) in credentials {
+            if value.trim().is_empty() {
+                errors.push(ConfigError::MissingSetting(setting.to_owned()));
+            }
+        }
 
-        let admins = input::<String>()
-            .msg("Admin users (separate multiple names by a comma): ")
-            .get();
+        if self.twitch.channels.is_empty() {
+            errors.push(ConfigError::MissingSetting("twitch.channels".to_owned()));
+        }
+
+        let remote_keys =
+            std::iter::once(("belabox.remote_key".to_owned(), &self.belabox.remote_key)).chain(
+                self.belabox.devices.iter().map(|(name, d)| {
+                    (
+                        format!("belabox.devices.{}.remote_key", name),
+                        &d.remote_key,
+                    )
+                }),
+            );
+
+        // The setting is named instead of the key, the output might end up in
+        // CI logs
+        for (setting, key) in remote_keys {
+            let key = key.trim();
+
+            if key.is_empty() {
+                errors.push(ConfigError::MissingSetting(setting));
+            } else if key.contains(['?', '=', '/', ':']) || key.contains(char::is_whitespace) {
+                errors.push(ConfigError::MalformedRemoteKey(setting));
+            }
+        }
+
+        let interface_names = std::iter::once(&self.belabox.custom_interface_name).chain(
+            self.belabox
+                .devices
+                .values()
+                .map(|d| &d.custom_interface_name),
+        );
 
-        if !admins.is_empty() {
-            for admin in admins.split(',') {
-                twitch.admins.push(admin.trim().to_lowercase());
+        for names in interface_names {
+            errors.extend(check_interface_names(names));
+        }
+
+        let permissions = self
+            .commands
+            .iter()
+            .map(|(command, info)| (format!("{:?}", command), &info.permission))
+            .chain(
+                self.twitch
+                    .user_overrides
+                    .iter()
+                    .map(|(user, p)| (user.to_owned(), p)),
+            );
+
+        for (used_by, permission) in permissions {
+            if let Permission::Group(group) = permission {
+                if !self.twitch.groups.contains_key(group) {
+                    errors.push(ConfigError::UnknownGroup {
+                        group: group.to_owned(),
+                        used_by,
+                    });
+                }
             }
         }
 
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        // The commands and interfaces are in no particular order
+        errors.sort_by_key(|e| e.to_string());
+
+        Err(errors)
+    }
+
+    /// Changes the config file in place.
+    ///
+    /// The file is read again so environment overrides are not written to it.
+    pub fn update<P, F>(path: P, f: F) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&mut Settings),
+    {
+        let path = path.as_ref();
+        let file = std::fs::read_to_string(path)?;
+        let mut config = ConfigFormat::from_path(path).deserialize(&file)?;
+
+        f(&mut config);
+
+        config.save(path)
+    }
+
+    /// Saves the config, the format is based on the file extension. The
+    /// file is replaced atomically, see [`write_atomic`].
+    pub fn save<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let format = ConfigFormat::from_path(path);
+        let contents = format.serialize(self)?;
+
+        // The config directory doesn't exist on the first run
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        write_atomic(path, contents.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Asks for the settings in the terminal and saves them to the given path.
+    pub async fn ask_for_settings<P>(path: P) -> Result<Self, ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+
+        let mut custom_interface_name = HashMap::new();
+        custom_interface_name.insert("eth0".to_string(), "eth0".to_string());
+        custom_interface_name.insert("usb0".to_string(), "usb0".to_string());
+        custom_interface_name.insert("wlan0".to_string(), "wlan0".to_string());
+
         let mut commands = HashMap::new();
-        default_chat_commands(&mut commands);
+        default_chat_commands(&mut commands, DEFAULT_COMMAND_PREFIX);
+
+        let mut messages = BTreeMap::new();
+        default_messages(&mut messages);
 
         let mut settings = Self {
-            belabox,
-            twitch,
+            version: CONFIG_VERSION,
+            belabox: Belabox {
+                custom_interface_name,
+                ..Default::default()
+            },
             commands,
+            messages,
+            ..Default::default()
         };
 
-        std::fs::write(CONFIG_FILE_NAME, serde_json::to_string_pretty(&settings)?)?;
+        ask_settings(&mut settings);
+        settings.save_setup(path).await?;
 
-        // FIXME: Does not work on windows
-        print!("\x1B[2J");
+        clear_terminal();
 
-        let mut path = std::env::current_dir()?;
-        path.push(CONFIG_FILE_NAME);
-        println!(
-            "Saved settings to {} in {}",
-            CONFIG_FILE_NAME,
-            path.display()
-        );
+        let full_path = std::env::current_dir()?.join(path);
+        println!("Saved settings to {}", full_path.display());
 
         lowercase_settings(&mut settings);
+        settings.path = Some(path.to_path_buf());
 
         Ok(settings)
     }
+
+    /// Asks for the settings again with the current ones as defaults, so
+    /// pressing enter keeps a setting. Runs the first time setup when there
+    /// is no config yet.
+    ///
+    /// The file is read without the environment overrides so they are not
+    /// written to it.
+    pub async fn reconfigure<P>(path: P) -> Result<Self, ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+
+        let mut settings = match std::fs::read_to_string(path) {
+            Ok(file) => ConfigFormat::from_path(path).deserialize(&file)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Self::ask_for_settings(path).await;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        ask_settings(&mut settings);
+        settings.save_setup(path).await?;
+
+        clear_terminal();
+
+        let full_path = std::env::current_dir()?.join(path);
+        println!("Saved settings to {}", full_path.display());
+
+        Self::load(path)
+    }
+
+    /// Saves the settings of the setup, a failed write is tried again a few
+    /// times. When it keeps failing the config is printed, so the entered
+    /// settings can still be saved by hand.
+    async fn save_setup(&self, path: &Path) -> Result<(), ConfigError> {
+        let mut attempt = 1;
+        let mut wait = SETUP_SAVE_WAIT;
+
+        let e = loop {
+            match self.save(path) {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt >= SETUP_SAVE_ATTEMPTS => break e,
+                Err(e) => {
+                    warn!(
+                        ?e,
+                        attempt,
+                        ?wait,
+                        "failed to save the config, trying again"
+                    );
+                    tokio::time::sleep(wait).await;
+                    attempt += 1;
+                    wait *= 2;
+                }
+            }
+        };
+
+        error!(?e, path = %path.display(), "failed to save the config");
+
+        if let Ok(contents) = ConfigFormat::from_path(path).serialize(self) {
+            println!(
+                "\nThe settings couldn't be saved to {}, save this as the config file to keep them. It contains your oauth, don't share it.\n\n{}",
+                path.display(),
+                contents
+            );
+        }
+
+        Err(e)
+    }
 }
 
-/// Lowercase settings which should always be lowercase
-fn lowercase_settings(settings: &mut Settings) {
+/// Asks for the settings of the first time setup in the terminal, the current
+/// values are the defaults.
+fn ask_settings(settings: &mut Settings) {
+    let belabox = &mut settings.belabox;
+    let has_key = !belabox.remote_key.is_empty();
+
+    println!("Please paste your BELABOX Cloud remote URL below");
+
+    loop {
+        let msg = if has_key {
+            "URL (press enter to keep the current key): "
+        } else {
+            "URL: "
+        };
+        let url: String = input().msg(msg).default(String::new()).get();
+
+        if url.trim().is_empty() && has_key {
+            break;
+        }
+
+        match parse_remote_key(&url) {
+            Ok(key) => {
+                belabox.remote_key = key;
+                break;
+            }
+            Err(e) => println!("{}, please try again", e),
+        }
+    }
+
+    println!("\nDo you want to receive automatic chat messages about:");
+
+    let monitor = &mut belabox.monitor;
+    monitor.modems = ask_y_or_n("The status of your modems", monitor.modems);
+    monitor.notifications = ask_y_or_n("The belaUI notifications", monitor.notifications);
+    monitor.ups = ask_y_or_n("The status of your UPS", monitor.ups);
+
+    if monitor.ups {
+        monitor.ups_plugged_in = input()
+            .msg(format!(
+                "UPS charging threshold (default {} V): ",
+                monitor.ups_plugged_in
+            ))
+            .err("Please enter a number")
+            .default(monitor.ups_plugged_in)
+            .get();
+    }
+
+    println!("\nPlease enter your Twitch details below");
+
+    let twitch = &mut settings.twitch;
+    twitch.bot_username = ask_with_current("Bot username", &twitch.bot_username);
+
+    // The oauth is a secret, so it's never shown
+    let oauth_msg = if twitch.bot_oauth.is_empty() {
+        "(You can generate an Oauth here: https://twitchapps.com/tmi/)\nBot oauth: "
+    } else {
+        "(You can generate an Oauth here: https://twitchapps.com/tmi/)\nBot oauth (press enter to keep the current one): "
+    };
+    let oauth: String = input().msg(oauth_msg).default(String::new()).get();
+
+    if !oauth.trim().is_empty() {
+        twitch.bot_oauth = oauth.trim().to_string();
+    }
+
+    let channels = ask_with_current(
+        "Channel names (separate multiple names by a comma)",
+        &twitch.channels.join(", "),
+    );
+    twitch.channels = split_names(&channels);
+
+    let admins = ask_with_current(
+        "Admin users (separate multiple names by a comma)",
+        &twitch.admins.join(", "),
+    );
+    twitch.admins = split_names(&admins);
+}
+
+/// Asks a yes or no question, enter picks the current answer.
+fn ask_y_or_n(question: &str, current: bool) -> bool {
+    let is_y_or_n = |x: &String| x.to_lowercase() == "y" || x.to_lowercase() == "n";
+    let (options, default) = if current { ("Y/n", "y") } else { ("y/N", "n") };
+
+    input_to_bool(
+        input()
+            .msg(format!("{} ({}): ", question, options))
+            .add_test(is_y_or_n)
+            .err("Please enter y or n: ")
+            .default(default.to_string())
+            .get(),
+    )
+}
+
+/// Asks for a value and shows the current one, enter keeps it.
+fn ask_with_current(question: &str, current: &str) -> String {
+    let msg = if current.is_empty() {
+        format!("{}: ", question)
+    } else {
+        format!("{} ({}): ", question, current)
+    };
+
+    input().msg(msg).default(current.to_string()).get()
+}
+
+/// Lowercase names from a comma separated list.
+fn split_names(names: &str) -> Vec<String> {
+    names
+        .split(',')
+        .map(|n| n.trim().to_lowercase())
+        .filter(|n| !n.is_empty())
+        .collect()
+}
+
+/// Lowercase settings which should always be lowercase.
+///
+/// Returns `true` if any setting was changed.
+fn lowercase_settings(settings: &mut Settings) -> bool {
     let Twitch {
         bot_username,
         bot_oauth,
-        channel,
+        channels,
         admins,
+        user_overrides,
         ..
     } = &mut settings.twitch;
 
-    *channel = channel.to_lowercase();
-    *bot_oauth = bot_oauth.to_lowercase();
-    *bot_username = bot_username.to_lowercase();
+    // File paths can contain uppercase characters
+    let mut changed = secret_file(bot_oauth).is_none() && lowercase(bot_oauth);
+    changed |= lowercase(bot_username);
+
+    for channel in channels {
+        changed |= lowercase(channel);
+    }
 
     for user in admins {
-        *user = user.to_lowercase();
+        changed |= lowercase(user);
+    }
+
+    if user_overrides.keys().any(|u| u.to_lowercase() != *u) {
+        *user_overrides = user_overrides
+            .drain()
+            .map(|(user, permission)| (user.to_lowercase(), permission))
+            .collect();
+        changed = true;
     }
 
     for info in settings.commands.values_mut() {
-        info.command = info.command.to_lowercase();
+        changed |= lowercase(&mut info.command);
+
+        for alias in &mut info.aliases {
+            changed |= lowercase(alias);
+        }
+    }
+
+    changed
+}
+
+/// Fails when a trigger or alias belongs to more than one command, only one
+/// of them would run.
+fn check_duplicate_triggers(
+    commands: &HashMap<BotCommand, CommandInformation>,
+) -> Result<(), ConfigError> {
+    let mut triggers: BTreeMap<String, BTreeSet<&BotCommand>> = BTreeMap::new();
+
+    for (command, info) in commands {
+        for trigger in std::iter::once(&info.command).chain(&info.aliases) {
+            triggers
+                .entry(trigger.to_lowercase())
+                .or_default()
+                .insert(command);
+        }
+    }
+
+    match triggers.into_iter().find(|(_, c)| c.len() > 1) {
+        Some((trigger, commands)) => Err(ConfigError::DuplicateTrigger {
+            trigger,
+            commands: commands
+                .iter()
+                .map(|c| format!("{:?}", c))
+                .collect::<Vec<_>>()
+                .join(", "),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Trims the interfaces and custom names and removes duplicates, warns when
+/// different interfaces share a custom name because commands can only target
+/// one of them.
+///
+/// An interface with more than one custom name is an error.
+///
+/// Returns `true` if any name was changed.
+fn normalize_interface_names(names: &mut HashMap<String, String>) -> Result<bool, ConfigError> {
+    let mut entries = names.drain().collect::<Vec<_>>();
+    entries.sort();
+
+    let mut changed = false;
+
+    for (interface, name) in entries {
+        let (trimmed_interface, trimmed_name) = (interface.trim(), name.trim());
+        changed |= trimmed_interface != interface || trimmed_name != name;
+
+        match names.get(trimmed_interface) {
+            Some(existing) if existing == trimmed_name => {
+                warn!(
+                    interface = trimmed_interface,
+                    name = trimmed_name,
+                    "duplicate custom interface name, removing it"
+                );
+                changed = true;
+            }
+            Some(existing) => {
+                return Err(ConfigError::AmbiguousInterfaceName {
+                    interface: trimmed_interface.to_owned(),
+                    names: format!("{}, {}", existing, trimmed_name),
+                });
+            }
+            None => {
+                names.insert(trimmed_interface.to_owned(), trimmed_name.to_owned());
+            }
+        }
+    }
+
+    let mut by_name: BTreeMap<String, Vec<&String>> = BTreeMap::new();
+    for (interface, name) in names.iter() {
+        by_name
+            .entry(name.to_lowercase())
+            .or_default()
+            .push(interface);
+    }
+
+    for (name, mut interfaces) in by_name {
+        if interfaces.len() > 1 {
+            interfaces.sort();
+            warn!(
+                name,
+                ?interfaces,
+                "Multiple interfaces have the same custom name, commands can only target one of them"
+            );
+        }
+    }
+
+    Ok(changed)
+}
+
+/// Empty custom names, and names which are shared so commands can only
+/// target one of the interfaces.
+fn check_interface_names(names: &HashMap<String, String>) -> Vec<ConfigError> {
+    let mut errors = Vec::new();
+    let mut by_name: BTreeMap<String, BTreeSet<&String>> = BTreeMap::new();
+
+    for (interface, name) in names {
+        if name.trim().is_empty() {
+            errors.push(ConfigError::EmptyInterfaceName(interface.to_owned()));
+            continue;
+        }
+
+        by_name
+            .entry(name.to_lowercase())
+            .or_default()
+            .insert(interface);
+    }
+
+    for (name, interfaces) in by_name {
+        if interfaces.len() > 1 {
+            errors.push(ConfigError::SharedInterfaceName {
+                name,
+                interfaces: interfaces
+                    .into_iter()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            });
+        }
     }
+
+    errors
+}
+
+/// Overrides secrets with their environment variables when set.
+///
+/// Returns `true` if any setting was overridden.
+fn env_overrides(settings: &mut Settings) -> bool {
+    let overrides = [
+        (ENV_TWITCH_USERNAME, &mut settings.twitch.bot_username),
+        (ENV_TWITCH_OAUTH, &mut settings.twitch.bot_oauth),
+        (ENV_REMOTE_KEY, &mut settings.belabox.remote_key),
+    ];
+
+    let mut changed = false;
+
+    for (key, value) in overrides {
+        if let Ok(env) = std::env::var(key) {
+            if !env.is_empty() {
+                *value = env;
+                changed = true;
+            }
+        }
+    }
+
+    changed
+}
+
+/// Gets the path of a secret given as a `file:` reference.
+pub fn secret_file(value: &str) -> Option<&Path> {
+    value
+        .strip_prefix(SECRET_FILE_PREFIX)
+        .map(|p| Path::new(p.trim()))
+}
+
+/// Replaces secrets given as `file:` references with the contents of the
+/// file.
+///
+/// Returns `true` if any secret was read from a file.
+fn resolve_secret_files(settings: &mut Settings) -> Result<bool, ConfigError> {
+    let mut secrets = vec![
+        &mut settings.twitch.bot_oauth,
+        &mut settings.belabox.remote_key,
+    ];
+    secrets.extend(
+        settings
+            .twitch
+            .channel_points
+            .as_mut()
+            .map(|c| &mut c.oauth),
+    );
+    secrets.extend(
+        settings
+            .belabox
+            .devices
+            .values_mut()
+            .map(|d| &mut d.remote_key),
+    );
+
+    let mut changed = false;
+
+    for secret in secrets {
+        let path = match secret_file(secret) {
+            Some(p) => p.to_path_buf(),
+            None => continue,
+        };
+
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(c) => c,
+            Err(source) => return Err(ConfigError::SecretFile { path, source }),
+        };
+
+        *secret = contents.trim_end_matches(['\r', '\n']).to_owned();
+        changed = true;
+    }
+
+    Ok(changed)
+}
+
+/// Sets a secret, writes it to the file instead when the secret is a `file:`
+/// reference.
+pub fn set_secret(secret: &mut String, value: &str) -> std::io::Result<()> {
+    match secret_file(secret) {
+        Some(path) => std::fs::write(path, value),
+        None => {
+            *secret = value.to_owned();
+            Ok(())
+        }
+    }
+}
+
+/// Lowercases the value in place and returns `true` if it was changed.
+fn lowercase(value: &mut String) -> bool {
+    let lower = value.to_lowercase();
+
+    if *value == lower {
+        return false;
+    }
+
+    *value = lower;
+    true
+}
+
+/// Writes the file to a temporary file next to it first which then replaces
+/// it, so a crash can't leave a truncated file. The file keeps its
+/// permissions, a new one can only be read by its owner on unix because it
+/// can contain secrets.
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    if let Err(e) = write_replacement(path, &tmp_path, contents) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+
+    // The rename is only durable once the directory is written as well
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+
+        std::fs::File::open(dir)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Writes `tmp_path` and renames it to `path`.
+fn write_replacement(path: &Path, tmp_path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    // A left over file would keep its permissions
+    let _ = std::fs::remove_file(tmp_path);
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(tmp_path)?;
+
+    if let Ok(metadata) = std::fs::metadata(path) {
+        file.set_permissions(metadata.permissions())?;
+    }
+
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    std::fs::rename(tmp_path, path)
+}
+
+/// Gets the remote key from a BELABOX Cloud remote URL.
+fn parse_remote_key(url: &str) -> Result<String, ConfigError> {
+    let key = url
+        .trim()
+        .split("?key=")
+        .nth(1)
+        .and_then(|k| k.split(['&', '#']).next())
+        .map(str::trim)
+        .unwrap_or_default();
+
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return Err(ConfigError::InvalidRemoteKey);
+    }
+
+    Ok(key.to_string())
+}
+
+/// Clears the terminal, does nothing when the output is not a terminal or the
+/// terminal doesn't support escape codes.
+fn clear_terminal() {
+    let mut stdout = std::io::stdout();
+
+    if !stdout.is_terminal() {
+        return;
+    }
+
+    // Older Windows consoles need escape codes to be enabled first
+    #[cfg(windows)]
+    if nu_ansi_term::enable_ansi_support().is_err() {
+        return;
+    }
+
+    // Clear the screen and move the cursor to the top left
+    let _ = write!(stdout, "\x1B[2J\x1B[1;1H");
+    let _ = stdout.flush();
 }
 
 /// Converts y or n to bool.
@@ -258,89 +1804,580 @@ fn input_to_bool(confirm: String) -> bool {
     confirm.to_lowercase() == "y"
 }
 
-// Insert default commands if they don't exist
-fn default_chat_commands(commands: &mut HashMap<BotCommand, CommandInformation>) {
-    commands
-        .entry(BotCommand::Start)
-        .or_insert(CommandInformation {
-            command: "!bbstart".to_string(),
-            permission: Permission::Broadcaster,
-        });
+/// Aliases of new default commands, a longer name for short triggers.
+const DEFAULT_ALIASES: &[(BotCommand, &str)] = &[
+    (BotCommand::AudioDelay, "!bbsync"),
+    (BotCommand::Latency, "!bblatency"),
+];
 
-    commands
-        .entry(BotCommand::Stop)
-        .or_insert(CommandInformation {
-            command: "!bbstop".to_string(),
-            permission: Permission::Broadcaster,
-        });
+/// The usage of the commands which take arguments.
+const DEFAULT_USAGE: &[(BotCommand, &str)] = &[
+    (BotCommand::Audio, "{command} (source)"),
+    (
+        BotCommand::AudioDelay,
+        "{command} (delay in ms, -2000 - 2000)",
+    ),
+    (
+        BotCommand::Bitrate,
+        "{command} (bitrate in kbps, 500 - 12000)",
+    ),
+    (BotCommand::Label, "{command} (interface) (name)"),
+    (BotCommand::Latency, "{command} (latency in ms, 100 - 4000)"),
+    (BotCommand::ModemRestart, "{command} (interface)"),
+    (BotCommand::ModemToggle, "{command} (interface) (on/off)"),
+    (BotCommand::Network, "{command} (interface)"),
+    (BotCommand::Output, "{command} (name)"),
+    (BotCommand::Raw, "{command} (JSON object)"),
+    (BotCommand::ReadOnly, "{command} (on/off)"),
+    (BotCommand::Stop, "{command} (idle)"),
+];
 
-    commands
-        .entry(BotCommand::Stats)
-        .or_insert(CommandInformation {
-            command: "!bbs".to_string(),
-            permission: Permission::Public,
-        });
+/// Insert default commands if they don't exist, commands without a usage get
+/// the default one. The `!` of the default triggers is replaced with `prefix`.
+///
+/// Returns `true` if any command was inserted or changed.
+pub(crate) fn default_chat_commands(
+    commands: &mut HashMap<BotCommand, CommandInformation>,
+    prefix: &str,
+) -> bool {
+    let defaults = [
+        (BotCommand::Start, "!bbstart", Permission::Broadcaster),
+        (BotCommand::Stop, "!bbstop", Permission::Broadcaster),
+        (BotCommand::Stats, "!bbs", Permission::Public),
+        (BotCommand::Restart, "!bbrs", Permission::Broadcaster),
+        (BotCommand::Poweroff, "!bbpo", Permission::Broadcaster),
+        (BotCommand::Bitrate, "!bbb", Permission::Broadcaster),
+        (BotCommand::Sensor, "!bbsensor", Permission::Public),
+        (BotCommand::Network, "!bbt", Permission::Broadcaster),
+        (BotCommand::Label, "!bblabel", Permission::Broadcaster),
+        (BotCommand::Output, "!bbout", Permission::Broadcaster),
+        (BotCommand::Latency, "!bbl", Permission::Broadcaster),
+        (BotCommand::AudioDelay, "!bbd", Permission::Broadcaster),
+        (BotCommand::Pipeline, "!bbp", Permission::Broadcaster),
+        (BotCommand::AudioSrc, "!bba", Permission::Broadcaster),
+        (BotCommand::Audio, "!bbaudio", Permission::Broadcaster),
+        (BotCommand::Help, "!bbhelp", Permission::Public),
+        (BotCommand::History, "!bbhist", Permission::Public),
+        (BotCommand::Signal, "!bbsig", Permission::Public),
+        (BotCommand::Top, "!bbtop", Permission::Broadcaster),
+        (BotCommand::Version, "!bbversion", Permission::Moderator),
+        (BotCommand::BotUptime, "!bbup", Permission::Public),
+        (BotCommand::Connection, "!bbip", Permission::Broadcaster),
+        (BotCommand::Raw, "!bbraw", Permission::Broadcaster),
+        (BotCommand::Reload, "!bbreload", Permission::Broadcaster),
+        (BotCommand::ReadOnly, "!bbreadonly", Permission::Broadcaster),
+        (
+            BotCommand::ModemRestart,
+            "!bbmodem",
+            Permission::Broadcaster,
+        ),
+        (
+            BotCommand::ModemToggle,
+            "!bbmodemset",
+            Permission::Broadcaster,
+        ),
+    ];
 
-    commands
-        .entry(BotCommand::Restart)
-        .or_insert(CommandInformation {
-            command: "!bbrs".to_string(),
-            permission: Permission::Broadcaster,
-        });
+    let mut inserted = false;
 
-    commands
-        .entry(BotCommand::Poweroff)
-        .or_insert(CommandInformation {
-            command: "!bbpo".to_string(),
-            permission: Permission::Broadcaster,
-        });
+    for (command, trigger, permission) in defaults {
+        let aliases = DEFAULT_ALIASES
+            .iter()
+            .filter(|(c, _)| *c == command)
+            .map(|(_, alias)| with_prefix(alias, prefix))
+            .collect();
 
-    commands
-        .entry(BotCommand::Bitrate)
-        .or_insert(CommandInformation {
-            command: "!bbb".to_string(),
-            permission: Permission::Broadcaster,
-        });
+        commands.entry(command).or_insert_with(|| {
+            inserted = true;
 
-    commands
-        .entry(BotCommand::Sensor)
-        .or_insert(CommandInformation {
-            command: "!bbsensor".to_string(),
-            permission: Permission::Public,
+            CommandInformation {
+                command: with_prefix(trigger, prefix),
+                enabled: true,
+                aliases,
+                permission,
+                cooldown_secs: None,
+                user_cooldown_secs: None,
+                reply_mode: ReplyMode::Public,
+                usage: None,
+                quiet_success: false,
+            }
         });
+    }
 
-    commands
-        .entry(BotCommand::Network)
-        .or_insert(CommandInformation {
-            command: "!bbt".to_string(),
-            permission: Permission::Broadcaster,
-        });
+    for (command, usage) in DEFAULT_USAGE {
+        if let Some(info) = commands.get_mut(command) {
+            if info.usage.is_none() {
+                info.usage = Some(usage.to_string());
+                inserted = true;
+            }
+        }
+    }
 
-    commands
-        .entry(BotCommand::Latency)
-        .or_insert(CommandInformation {
-            command: "!bbl".to_string(),
-            permission: Permission::Broadcaster,
-        });
+    inserted
+}
 
-    commands
-        .entry(BotCommand::AudioDelay)
-        .or_insert(CommandInformation {
-            command: "!bbd".to_string(),
-            permission: Permission::Broadcaster,
-        });
+/// Replaces the `!` of a default trigger with the configured prefix.
+fn with_prefix(trigger: &str, prefix: &str) -> String {
+    format!("{}{}", prefix, trigger.trim_start_matches('!'))
+}
 
-    commands
-        .entry(BotCommand::Pipeline)
-        .or_insert(CommandInformation {
-            command: "!bbp".to_string(),
-            permission: Permission::Broadcaster,
-        });
+/// Upgrades the config to the current version one version at a time.
+///
+/// Returns `true` if the config was migrated.
+fn migrate(settings: &mut Settings) -> bool {
+    if settings.version > CONFIG_VERSION {
+        warn!(
+            version = settings.version,
+            supported = CONFIG_VERSION,
+            "config was written by a newer version of belabot"
+        );
+        return false;
+    }
 
-    commands
-        .entry(BotCommand::AudioSrc)
-        .or_insert(CommandInformation {
-            command: "!bba".to_string(),
-            permission: Permission::Broadcaster,
+    if settings.version == CONFIG_VERSION {
+        return false;
+    }
+
+    while settings.version < CONFIG_VERSION {
+        match settings.version {
+            // Version 1 only introduced the version field, the fields added
+            // before it have defaults.
+            0 => {}
+            // Version 2 renamed `twitch.channel` to `twitch.channels`, the old
+            // name is read by the serde alias and saved with the new name.
+            1 => {}
+            _ => unreachable!("missing config migration"),
+        }
+
+        settings.version += 1;
+        info!(version = settings.version, "Migrated config");
+    }
+
+    true
+}
+
+/// Returns `true` if any message was inserted.
+fn default_messages(messages: &mut BTreeMap<String, String>) -> bool {
+    let mut inserted = false;
+
+    for (key, template) in DEFAULT_TEMPLATES {
+        messages.entry(key.to_string()).or_insert_with(|| {
+            inserted = true;
+            template.to_string()
         });
+    }
+
+    inserted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_from_path() {
+        assert_eq!(ConfigFormat::from_path("config.json"), ConfigFormat::Json);
+        assert_eq!(ConfigFormat::from_path("config.toml"), ConfigFormat::Toml);
+        assert_eq!(ConfigFormat::from_path("config.yaml"), ConfigFormat::Yaml);
+        assert_eq!(ConfigFormat::from_path("config.YML"), ConfigFormat::Yaml);
+        assert_eq!(ConfigFormat::from_path("config"), ConfigFormat::Json);
+    }
+
+    #[test]
+    fn toml_roundtrip() {
+        roundtrip(ConfigFormat::Toml);
+    }
+
+    #[test]
+    fn yaml_roundtrip() {
+        roundtrip(ConfigFormat::Yaml);
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        roundtrip(ConfigFormat::Json);
+    }
+
+    #[test]
+    fn remote_key() {
+        let key = parse_remote_key(" https://cloud.belabox.net/#/?key=abc123\r\n").unwrap();
+        assert_eq!(key, "abc123");
+
+        let key = parse_remote_key("https://cloud.belabox.net/?key=abc123&x=1").unwrap();
+        assert_eq!(key, "abc123");
+
+        assert!(parse_remote_key("https://cloud.belabox.net/").is_err());
+        assert!(parse_remote_key("https://cloud.belabox.net/?key=").is_err());
+    }
+
+    #[test]
+    fn load_is_clean() {
+        let mut settings = Settings::default();
+        settings.twitch.channels = vec!["715209".to_string()];
+
+        assert!(default_chat_commands(&mut settings.commands, "!"));
+        assert!(!default_chat_commands(&mut settings.commands, "!"));
+
+        // Commands from older configs get the usage
+        let bitrate = settings.commands.get_mut(&BotCommand::Bitrate).unwrap();
+        bitrate.usage = None;
+        assert!(default_chat_commands(&mut settings.commands, "!"));
+        assert!(settings.commands[&BotCommand::Bitrate].usage.is_some());
+        assert!(default_messages(&mut settings.messages));
+        assert!(!default_messages(&mut settings.messages));
+        assert!(!lowercase_settings(&mut settings));
+
+        settings.twitch.admins.push("B3ck".to_string());
+        assert!(lowercase_settings(&mut settings));
+        assert_eq!(settings.twitch.admins, vec!["b3ck".to_string()]);
+    }
+
+    #[test]
+    fn unknown_settings() {
+        let config = r#"{
+            "future_setting": true,
+            "belabox": { "remote_key": "key", "new_field": 1 },
+            "twitch": { "bot_username": "715209", "bot_oauth": "oauth", "channels": [], "admins": [] },
+            "commands": {
+                "Stats": { "command": "!bbs", "permission": "Public" },
+                "Teleport": { "command": "!bbtp", "permission": "Public" }
+            }
+        }"#;
+
+        let settings = ConfigFormat::Json.deserialize(config).unwrap();
+        assert_eq!(settings.belabox.remote_key, "key");
+        assert_eq!(settings.commands.len(), 1);
+        assert!(settings.commands.contains_key(&BotCommand::Stats));
+    }
+
+    #[test]
+    fn permission_hierarchy() {
+        use Permission::*;
+
+        let roles = [Public, Subscriber, Vip, Moderator, Broadcaster];
+
+        for (i, needed) in roles.iter().enumerate() {
+            for (j, role) in roles.iter().enumerate() {
+                assert_eq!(needed.satisfies(role), j >= i, "{:?} by {:?}", needed, role);
+            }
+        }
+
+        let trusted = Group("trusted".to_string());
+        assert!(trusted.satisfies(&Broadcaster));
+        assert!(trusted.satisfies(&Group("trusted".to_string())));
+        assert!(!trusted.satisfies(&Group("other".to_string())));
+        assert!(!trusted.satisfies(&Moderator));
+        assert!(!Vip.satisfies(&trusted));
+        assert!(Public.satisfies(&trusted));
+    }
+
+    #[test]
+    fn duplicate_triggers() {
+        let mut commands = HashMap::new();
+        default_chat_commands(&mut commands, "!");
+        assert!(check_duplicate_triggers(&commands).is_ok());
+
+        commands.get_mut(&BotCommand::Stop).unwrap().aliases = vec!["!BBS".to_string()];
+
+        match check_duplicate_triggers(&commands) {
+            Err(ConfigError::DuplicateTrigger { trigger, commands }) => {
+                assert_eq!(trigger, "!bbs");
+                assert_eq!(commands, "Stats, Stop");
+            }
+            r => panic!("expected a duplicate trigger, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn validate() {
+        let mut settings = Settings::default();
+        settings.twitch.bot_username = "bot".to_string();
+        settings.twitch.bot_oauth = "oauth:abc".to_string();
+        settings.twitch.channels = vec!["715209".to_string()];
+        settings.belabox.remote_key = "abc".to_string();
+        default_chat_commands(&mut settings.commands, "!");
+        assert!(settings.validate().is_ok());
+
+        settings.belabox.remote_key = "https://cloud.belabox.net/remote?key=abc".to_string();
+        settings.twitch.bot_oauth = String::new();
+        settings
+            .commands
+            .get_mut(&BotCommand::Stop)
+            .unwrap()
+            .permission = Permission::Group("trusted".to_string());
+
+        let errors = settings
+            .validate()
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            errors,
+            [
+                "The group trusted of Stop is not in twitch.groups",
+                "belabox.remote_key is not a remote key, use only the part after ?key= of the remote URL",
+                "twitch.bot_oauth is not set",
+            ]
+        );
+    }
+
+    #[test]
+    fn command_prefix() {
+        let mut commands = HashMap::new();
+        commands.insert(
+            BotCommand::Start,
+            CommandInformation {
+                command: "!go".to_string(),
+                enabled: true,
+                aliases: Vec::new(),
+                permission: Permission::Broadcaster,
+                cooldown_secs: None,
+                user_cooldown_secs: None,
+                reply_mode: ReplyMode::Public,
+                usage: None,
+                quiet_success: false,
+            },
+        );
+
+        default_chat_commands(&mut commands, "$");
+
+        assert_eq!(commands[&BotCommand::Start].command, "!go");
+        assert_eq!(commands[&BotCommand::Stats].command, "$bbs");
+        assert_eq!(commands[&BotCommand::Latency].aliases, ["$bblatency"]);
+    }
+
+    #[test]
+    fn listen_addresses() {
+        let status: StatusServer =
+            serde_json::from_str(r#"{ "enabled": true, "address": "[::]:8080" }"#).unwrap();
+        assert!(status.address.is_ipv6());
+        assert_eq!(status.address.port(), 8080);
+
+        let metrics: Metrics =
+            serde_json::from_str(r#"{ "address": "192.168.1.2:9110" }"#).unwrap();
+        assert_eq!(metrics.address, SocketAddr::from(([192, 168, 1, 2], 9110)));
+
+        // A port alone is not enough
+        assert!(serde_json::from_str::<Metrics>(r#"{ "address": "9110" }"#).is_err());
+    }
+
+    #[test]
+    fn notification_targets() {
+        let notifications: Notifications = serde_json::from_str(
+            r#"{ "targets": { "modems": ["discord", "log"], "bitrate": ["chat"] } }"#,
+        )
+        .unwrap();
+        let targets = notifications.targets;
+
+        assert_eq!(
+            targets.modems,
+            Some(BTreeSet::from([
+                NotificationTarget::Discord,
+                NotificationTarget::Log
+            ]))
+        );
+        assert_eq!(
+            targets.bitrate,
+            Some(BTreeSet::from([NotificationTarget::Chat]))
+        );
+        assert_eq!(targets.stream, None);
+    }
+
+    #[test]
+    fn schema_command_names() {
+        let schema: serde_json::Value = serde_json::from_str(&json_schema()).unwrap();
+
+        let commands = &schema["properties"]["commands"];
+        assert_eq!(
+            commands["propertyNames"]["$ref"],
+            "#/definitions/BotCommand"
+        );
+
+        let names = schema["definitions"]["BotCommand"]["enum"]
+            .as_array()
+            .unwrap();
+        assert!(names.contains(&serde_json::json!("Stats")));
+    }
+
+    #[test]
+    fn single_channel() {
+        let config = r#"{
+            "belabox": {},
+            "twitch": {
+                "bot_username": "bot",
+                "bot_oauth": "oauth",
+                "channel": "715209",
+                "admins": []
+            },
+            "commands": {}
+        }"#;
+
+        let settings = ConfigFormat::Json.deserialize(config).unwrap();
+        assert_eq!(settings.twitch.channels, vec!["715209".to_string()]);
+    }
+
+    #[test]
+    fn interface_names() {
+        let map = |entries: &[(&str, &str)]| {
+            entries
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<String, String>>()
+        };
+
+        let mut names = map(&[("eth0", "ETH"), ("usb0", "USB")]);
+        assert!(!normalize_interface_names(&mut names).unwrap());
+
+        let mut names = map(&[("eth0", "ETH"), (" eth0", "ETH "), ("usb0", "USB")]);
+        assert!(normalize_interface_names(&mut names).unwrap());
+        assert_eq!(names, map(&[("eth0", "ETH"), ("usb0", "USB")]));
+
+        let mut names = map(&[("eth0", "ETH"), ("eth0 ", "LAN")]);
+        assert!(matches!(
+            normalize_interface_names(&mut names),
+            Err(ConfigError::AmbiguousInterfaceName { .. })
+        ));
+    }
+
+    #[test]
+    fn quiet_hours() {
+        let range = TimeRange::try_from("23:00-08:00".to_string()).unwrap();
+        assert!(range.contains(NaiveTime::from_hms_opt(23, 30, 0).unwrap()));
+        assert!(range.contains(NaiveTime::from_hms_opt(7, 59, 0).unwrap()));
+        assert!(!range.contains(NaiveTime::from_hms_opt(8, 0, 0).unwrap()));
+        assert!(TimeRange::try_from("23:00".to_string()).is_err());
+
+        let quiet = QuietHours {
+            timezone: Some(chrono_tz::Europe::Amsterdam),
+            ranges: vec![range],
+        };
+
+        // 21:30 UTC is 23:30 in the summer and 22:30 in the winter
+        let summer = "2024-07-01T21:30:00Z".parse().unwrap();
+        let winter = "2024-01-01T21:30:00Z".parse().unwrap();
+        assert!(quiet.is_quiet(summer));
+        assert!(!quiet.is_quiet(winter));
+    }
+
+    #[test]
+    fn permission_group() {
+        let config = r#"{
+            "belabox": {},
+            "twitch": {
+                "bot_username": "bot",
+                "bot_oauth": "oauth",
+                "channels": ["715209"],
+                "admins": [],
+                "groups": { "trusted": ["b3ck"] }
+            },
+            "commands": {
+                "Start": { "command": "!bbstart", "permission": { "Group": "trusted" } }
+            }
+        }"#;
+
+        let settings = ConfigFormat::Json.deserialize(config).unwrap();
+        assert_eq!(settings.twitch.groups["trusted"], vec!["b3ck".to_string()]);
+        assert!(matches!(
+            &settings.commands[&BotCommand::Start].permission,
+            Permission::Group(g) if g == "trusted"
+        ));
+    }
+
+    #[test]
+    fn migrate_version() {
+        let mut settings = Settings::default();
+        assert_eq!(settings.version, 0);
+
+        assert!(migrate(&mut settings));
+        assert_eq!(settings.version, CONFIG_VERSION);
+        assert!(!migrate(&mut settings));
+
+        settings.version = CONFIG_VERSION + 1;
+        assert!(!migrate(&mut settings));
+        assert_eq!(settings.version, CONFIG_VERSION + 1);
+    }
+
+    #[test]
+    fn save_replaces_file() {
+        let dir = std::env::temp_dir().join(format!("belabot-save-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        std::fs::write(&path, "old").unwrap();
+
+        let mut settings = Settings::default();
+        settings.twitch.channels = vec!["715209".to_string()];
+        settings.save(&path).unwrap();
+
+        let saved = Settings::load(&path).unwrap();
+        assert_eq!(saved.twitch.channels, vec!["715209".to_string()]);
+        assert!(!dir.join("config.json.tmp").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("belabot-mode-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        Settings::default().save(&path).unwrap();
+        assert_eq!(mode(&path), 0o600);
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+        Settings::default().save(&path).unwrap();
+        assert_eq!(mode(&path), 0o640);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn secret_files() {
+        let dir = std::env::temp_dir().join(format!("belabot-secrets-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let oauth = dir.join("Oauth");
+        std::fs::write(&oauth, "oauth:secret\n").unwrap();
+
+        let mut settings = Settings::default();
+        settings.twitch.bot_oauth = format!("file:{}", oauth.display());
+        settings.belabox.remote_key = "key".to_string();
+
+        assert!(!lowercase_settings(&mut settings));
+        assert!(resolve_secret_files(&mut settings).unwrap());
+        assert_eq!(settings.twitch.bot_oauth, "oauth:secret");
+        assert_eq!(settings.belabox.remote_key, "key");
+
+        settings.belabox.remote_key = format!("file:{}", dir.join("missing").display());
+        assert!(matches!(
+            resolve_secret_files(&mut settings),
+            Err(ConfigError::SecretFile { .. })
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn roundtrip(format: ConfigFormat) {
+        let mut settings = Settings::default();
+        settings.belabox.remote_key = "key".to_string();
+        settings.twitch.channels = vec!["715209".to_string()];
+        default_chat_commands(&mut settings.commands, "!");
+        default_messages(&mut settings.messages);
+
+        let serialized = format.serialize(&settings).unwrap();
+
+        let parsed = format.deserialize(&serialized).unwrap();
+        assert_eq!(parsed.belabox.remote_key, "key");
+        assert_eq!(parsed.twitch.channels, vec!["715209".to_string()]);
+        assert_eq!(parsed.commands.len(), settings.commands.len());
+        assert_eq!(parsed.commands[&BotCommand::Start].command, "!bbstart");
+        assert!(parsed.commands[&BotCommand::Start].enabled);
+        assert_eq!(parsed.messages, settings.messages);
+    }
 }
@@ -0,0 +1,108 @@
+use serde_json::json;
+use tracing::{debug, error};
+
+/// Embed colors
+const GREEN: u32 = 0x2ecc71;
+const ORANGE: u32 = 0xe67e22;
+const RED: u32 = 0xe74c3c;
+
+/// Stream events which can be sent to Discord.
+#[derive(Debug, Clone)]
+pub enum Event {
+    StreamStarted,
+    StreamStopped,
+    /// Modems which connected or disconnected
+    Modems(String),
+    /// The total bitrate in kbps
+    BitrateLow(u64),
+    /// The SoC temperature in celsius
+    Temperature(f64),
+    /// A connection to or from BELABOX Cloud was lost
+    Disconnected(String),
+    /// A lost connection is back
+    Reconnected(String),
+}
+
+impl Event {
+    fn title(&self) -> &'static str {
+        match self {
+            Event::StreamStarted => "Stream started",
+            Event::StreamStopped => "Stream stopped",
+            Event::Modems(_) => "Modems changed",
+            Event::BitrateLow(_) => "Low bitrate",
+            Event::Temperature(_) => "High temperature",
+            Event::Disconnected(_) => "Disconnected",
+            Event::Reconnected(_) => "Reconnected",
+        }
+    }
+
+    fn description(&self) -> String {
+        match self {
+            Event::StreamStarted => "The BELABOX started streaming".to_string(),
+            Event::StreamStopped => "The BELABOX stopped streaming".to_string(),
+            Event::Modems(message) | Event::Disconnected(message) | Event::Reconnected(message) => {
+                message.to_owned()
+            }
+            Event::BitrateLow(kbps) => format!("The bitrate dropped to {} kbps", kbps),
+            Event::Temperature(celsius) => format!("The temperature is {} °C", celsius),
+        }
+    }
+
+    fn color(&self) -> u32 {
+        match self {
+            Event::StreamStarted | Event::Reconnected(_) => GREEN,
+            Event::StreamStopped | Event::Temperature(_) | Event::Disconnected(_) => RED,
+            Event::Modems(_) | Event::BitrateLow(_) => ORANGE,
+        }
+    }
+}
+
+/// Sends events to a Discord webhook.
+#[derive(Debug, Clone)]
+pub struct Discord {
+    client: reqwest::Client,
+    webhook_url: String,
+}
+
+impl Discord {
+    pub fn new(webhook_url: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            webhook_url,
+        }
+    }
+
+    /// Sends the event in the background, failures are only logged.
+    pub fn send(&self, author: &str, event: Event) {
+        let discord = self.clone();
+        let author = author.to_owned();
+
+        tokio::spawn(async move {
+            if let Err(e) = discord.post(&author, &event).await {
+                error!(?e, ?event, "error sending event to discord");
+            }
+        });
+    }
+
+    async fn post(&self, author: &str, event: &Event) -> Result<(), reqwest::Error> {
+        debug!(?event, "sending event to discord");
+
+        let body = json!({
+            "embeds": [{
+                "author": { "name": author },
+                "title": event.title(),
+                "description": event.description(),
+                "color": event.color(),
+            }]
+        });
+
+        self.client
+            .post(&self.webhook_url)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
@@ -13,4 +13,13 @@ pub enum Error {
     TwitchValide(#[from] twitch_irc::validate::Error),
     #[error("Twitch error")]
     Twitch(#[from] twitch::TwitchError),
+    #[error("Status server error")]
+    Status(#[from] hyper::Error),
+    #[error("Failed to listen on {address}")]
+    Listen {
+        address: std::net::SocketAddr,
+        source: hyper::Error,
+    },
+    #[error("IO error")]
+    Io(#[from] std::io::Error),
 }
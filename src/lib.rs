@@ -1,14 +1,39 @@
+//! belabot controls a BELABOX from Twitch chat, and can be used as a library
+//! as well.
+//!
+//! The public API:
+//!
+//! - [`Settings`]: the config, loaded with [`Settings::load`]
+//! - [`Belabox`]: the BELABOX Cloud client, with [`belabox::Message`] for what
+//!   the BELABOX sends and [`belabox::Request`] for what can be sent to it
+//! - [`Twitch`]: the chat connection
+//! - [`CommandHandler`]: runs the chat commands on one or more
+//!   [`command_handler::Device`]s
+//! - [`Bot`]: all of the above together, as used by the binary
+//!
+//! The other public modules hold the types these use, such as
+//! [`templates::Templates`] for the chat messages.
+
 pub mod belabox;
 pub mod bot;
-mod command_handler;
+pub mod command_handler;
 pub mod config;
+pub mod discord;
 pub mod error;
+mod metrics;
 mod monitor;
+pub mod obs;
+#[cfg(feature = "mock")]
+pub mod repl;
+mod status;
+pub mod templates;
 pub mod twitch;
+pub mod update;
+pub mod usage;
 
 pub use belabox::Belabox;
 pub use bot::Bot;
-use command_handler::CommandHandler;
+pub use command_handler::CommandHandler;
 pub use config::Settings;
 use monitor::Monitor;
 pub use twitch::Twitch;
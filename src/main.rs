@@ -1,37 +1,206 @@
-use std::env;
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
 
 use anyhow::Result;
+use clap::Parser;
 
-use belabot::{Bot, Settings};
+use belabot::{
+    config::{self, ConfigError},
+    error::Error,
+    twitch::TwitchError,
+    Bot, Settings,
+};
+use tracing::{error, warn};
 use tracing_subscriber::filter::EnvFilter;
 
+use config::{LogFormat, Logging};
+
+/// Exit code when the config can't be loaded, or `--check` finds problems
+/// in it.
+const EXIT_INVALID_CONFIG: i32 = 1;
+
+/// Exit code when the Twitch credentials in the config can't be used.
+const EXIT_INVALID_CREDENTIALS: i32 = 2;
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Path to the config file, the format is based on the extension
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+    /// Log what commands would send to the BELABOX instead of sending it
+    #[arg(long)]
+    dry_run: bool,
+    /// Log in JSON instead of human readable text
+    #[arg(long)]
+    log_json: bool,
+    /// Log filter such as `belabot=debug`, `RUST_LOG` takes priority over it
+    #[arg(long)]
+    log_level: Option<String>,
+    /// Run the first time setup again, pressing enter keeps a setting
+    #[arg(long, alias = "reconfigure")]
+    setup: bool,
+    /// Print the JSON Schema of the config and exit
+    #[arg(long)]
+    dump_schema: bool,
+    /// Check the config for problems without changing it and exit, with a
+    /// nonzero code when there are any
+    #[arg(long)]
+    check: bool,
+    /// Type chat messages and see the replies, without Twitch and with a
+    /// mock BELABOX
+    #[cfg(feature = "mock")]
+    #[arg(long)]
+    repl: bool,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    if env::var("RUST_LOG").is_err() {
-        env::set_var("RUST_LOG", "belabot=info");
+    let args = Args::parse();
+
+    if args.dump_schema {
+        println!("{}", config::json_schema());
+        return Ok(());
     }
 
-    if cfg!(windows) {
-        tracing_subscriber::fmt()
-            .with_env_filter(EnvFilter::from_default_env())
-            .with_ansi(false)
-            .init();
-    } else {
-        tracing_subscriber::fmt::init();
+    let mut path = args.config.to_owned().unwrap_or_else(config::default_path);
+
+    let mut logging = Logging::read(&path);
+
+    if args.log_json {
+        logging.format = LogFormat::Json;
     }
 
-    let config = match Settings::load("config.json") {
-        Ok(c) => c,
-        Err(_) => Settings::ask_for_settings().await?,
+    if args.log_level.is_some() {
+        logging.level = args.log_level;
+    }
+
+    init_logging(&logging);
+
+    // Before moving the config, checking doesn't change anything
+    if args.check {
+        std::process::exit(check(&path));
+    }
+
+    #[cfg(feature = "mock")]
+    if args.repl {
+        belabot::repl::run(&path).await?;
+        return Ok(());
+    }
+
+    // Configs of older versions are in the working directory
+    if args.config.is_none() {
+        path = config::move_to_config_dir(path);
+    }
+
+    let mut config = if args.setup {
+        Settings::reconfigure(&path).await?
+    } else {
+        match Settings::load(&path) {
+            Ok(c) => c,
+            // Only a missing config is a first run, the setup would replace
+            // a config with a typo in it
+            Err(ConfigError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                Settings::ask_for_settings(&path).await?
+            }
+            Err(e) => {
+                error!(path = %path.display(), "{}, fix the config or run --setup", e);
+                std::process::exit(EXIT_INVALID_CONFIG);
+            }
+        }
     };
 
-    let bot = Bot::new(config).await?;
+    config.dry_run |= args.dry_run;
+
+    if config.dry_run {
+        warn!("Dry run, commands will not be sent to the BELABOX");
+    }
+
+    let mut bot = match Bot::new(config).await {
+        Ok(b) => b,
+        Err(Error::Twitch(e @ (TwitchError::InvalidOauth | TwitchError::MissingScopes(_)))) => {
+            error!("{}, check bot_oauth in the config", e);
+            std::process::exit(EXIT_INVALID_CREDENTIALS);
+        }
+        Err(e) => return Err(e.into()),
+    };
 
     // There is no way to recover when any of these stop, so stop the program
     tokio::select! {
-        _ = bot.bb_msg_handle => {}
-        _ = bot.tw_msg_handle => {}
+        _ = &mut bot.bb_msg_handle => {}
+        _ = &mut bot.tw_msg_handle => {}
+        _ = shutdown_signal() => {}
     };
 
+    bot.shutdown().await;
+
     Ok(())
 }
+
+/// Prints the problems of the config, returns the exit code.
+fn check(path: &Path) -> i32 {
+    let config = match Settings::read(path) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            return EXIT_INVALID_CONFIG;
+        }
+    };
+
+    match config.validate() {
+        Ok(()) => {
+            println!("{}: no problems found", path.display());
+            0
+        }
+        Err(errors) => {
+            for e in errors {
+                eprintln!("{}: {}", path.display(), e);
+            }
+
+            EXIT_INVALID_CONFIG
+        }
+    }
+}
+
+/// Waits for Ctrl-C, or SIGTERM on unix such as when systemd stops the bot.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = match signal(SignalKind::terminate()) {
+            Ok(s) => s,
+            Err(e) => {
+                warn!(?e, "failed to listen for SIGTERM");
+                let _ = tokio::signal::ctrl_c().await;
+                return;
+            }
+        };
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+fn init_logging(logging: &Logging) {
+    let filter = match env::var("RUST_LOG") {
+        Ok(_) => EnvFilter::from_default_env(),
+        Err(_) => EnvFilter::new(logging.level.as_deref().unwrap_or("belabot=info")),
+    };
+
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+
+    match logging.format {
+        LogFormat::Json => subscriber.json().flatten_event(true).init(),
+        LogFormat::Pretty => subscriber.with_ansi(!cfg!(windows)).init(),
+    }
+}
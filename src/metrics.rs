@@ -0,0 +1,267 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{atomic::AtomicU64, Arc},
+};
+
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use prometheus_client::{
+    encoding::{text, EncodeLabelSet},
+    metrics::{counter::Counter, family::Family, gauge::Gauge},
+    registry::Registry,
+};
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+use crate::{
+    command_handler::Device,
+    config::BotCommand,
+    error::Error,
+    status::{self, DeviceStatus},
+    usage::Usage,
+};
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct DeviceLabels {
+    device: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ModemLabels {
+    device: String,
+    interface: String,
+    name: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct CommandLabels {
+    command: String,
+}
+
+/// The state of a device at the time of the scrape.
+struct DeviceMetrics {
+    status: DeviceStatus,
+    /// How many times the websocket to BELABOX Cloud reconnected
+    reconnects: u64,
+}
+
+/// Binds the metrics server and serves it in the background.
+pub async fn spawn(
+    address: SocketAddr,
+    devices: Vec<Device>,
+    usage: Arc<Mutex<Usage>>,
+) -> Result<(), Error> {
+    let devices = Arc::new(devices);
+
+    let make_service = make_service_fn(move |_| {
+        let devices = devices.clone();
+        let usage = usage.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let devices = devices.clone();
+                let usage = usage.clone();
+                async move { Ok::<_, Infallible>(handle(request, &devices, &usage).await) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&address)
+        .map_err(|source| Error::Listen { address, source })?
+        .serve(make_service);
+    info!(%address, "Metrics server listening");
+
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error!(?e, "metrics server error");
+        }
+    });
+
+    Ok(())
+}
+
+async fn handle(
+    request: Request<Body>,
+    devices: &[Device],
+    usage: &Mutex<Usage>,
+) -> Response<Body> {
+    if request.method() != Method::GET {
+        return empty(StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    if request.uri().path() != "/metrics" {
+        return empty(StatusCode::NOT_FOUND);
+    }
+
+    let mut metrics = Vec::with_capacity(devices.len());
+
+    for device in devices {
+        let reconnects = device.bela_state.read().await.connections.saturating_sub(1);
+
+        metrics.push(DeviceMetrics {
+            status: status::device_status(device).await,
+            reconnects,
+        });
+    }
+
+    let commands = usage.lock().await.session.commands.to_owned();
+
+    let body = match render(&metrics, &commands) {
+        Ok(b) => b,
+        Err(e) => {
+            error!(?e, "error encoding metrics");
+            return empty(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let mut response = Response::new(Body::from(body));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static(CONTENT_TYPE),
+    );
+
+    response
+}
+
+/// Encodes the metrics in the OpenMetrics text format.
+fn render(
+    devices: &[DeviceMetrics],
+    commands: &HashMap<BotCommand, u64>,
+) -> Result<String, std::fmt::Error> {
+    let bitrate = Family::<DeviceLabels, Gauge>::default();
+    let streaming = Family::<DeviceLabels, Gauge>::default();
+    let online = Family::<DeviceLabels, Gauge>::default();
+    let temperature = Family::<DeviceLabels, Gauge<f64, AtomicU64>>::default();
+    let reconnects = Family::<DeviceLabels, Counter>::default();
+    let modem_bitrate = Family::<ModemLabels, Gauge>::default();
+    let command_count = Family::<CommandLabels, Counter>::default();
+
+    for device in devices {
+        let status = &device.status;
+        let labels = DeviceLabels {
+            device: status
+                .name
+                .to_owned()
+                .unwrap_or_else(|| "primary".to_owned()),
+        };
+
+        bitrate
+            .get_or_create(&labels)
+            .set(status.bitrate_kbps as i64);
+        streaming
+            .get_or_create(&labels)
+            .set(status.is_streaming as i64);
+        online.get_or_create(&labels).set(status.online as i64);
+        reconnects.get_or_create(&labels).inc_by(device.reconnects);
+
+        if let Some(t) = status.temperature_celsius {
+            temperature.get_or_create(&labels).set(t);
+        }
+
+        for modem in &status.modems {
+            let labels = ModemLabels {
+                device: labels.device.to_owned(),
+                interface: modem.interface.to_owned(),
+                name: modem.name.to_owned(),
+            };
+
+            modem_bitrate
+                .get_or_create(&labels)
+                .set(modem.bitrate_kbps as i64);
+        }
+    }
+
+    for (command, count) in commands {
+        let labels = CommandLabels {
+            command: format!("{:?}", command),
+        };
+
+        command_count.get_or_create(&labels).inc_by(*count);
+    }
+
+    let mut registry = Registry::with_prefix("belabot");
+    registry.register(
+        "bitrate_kbps",
+        "Total bitrate of the enabled modems",
+        bitrate,
+    );
+    registry.register(
+        "modem_bitrate_kbps",
+        "Bitrate of a single modem",
+        modem_bitrate,
+    );
+    registry.register(
+        "temperature_celsius",
+        "SoC temperature of the BELABOX",
+        temperature,
+    );
+    registry.register("streaming", "Whether the BELABOX is streaming", streaming);
+    registry.register("online", "Whether the BELABOX is online", online);
+    registry.register(
+        "belabox_reconnects",
+        "Reconnects to BELABOX Cloud since the bot started",
+        reconnects,
+    );
+    registry.register(
+        "commands",
+        "Chat commands used since the bot started",
+        command_count,
+    );
+
+    let mut body = String::new();
+    text::encode(&mut body, &registry)?;
+
+    Ok(body)
+}
+
+fn empty(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_metrics() {
+        let devices = [DeviceMetrics {
+            status: DeviceStatus {
+                name: None,
+                online: true,
+                is_streaming: true,
+                bitrate_kbps: 5000,
+                temperature_celsius: Some(45.5),
+                modems: vec![status::ModemStatus {
+                    name: "wifi".to_owned(),
+                    interface: "wlan0".to_owned(),
+                    ip: "192.168.1.2".to_owned(),
+                    enabled: true,
+                    bitrate_kbps: 5000,
+                }],
+            },
+            reconnects: 2,
+        }];
+        let commands = HashMap::from([(BotCommand::Stats, 3)]);
+
+        let body = render(&devices, &commands).unwrap();
+
+        assert!(body.contains("belabot_bitrate_kbps{device=\"primary\"} 5000"));
+        assert!(body.contains(
+            "belabot_modem_bitrate_kbps{device=\"primary\",interface=\"wlan0\",name=\"wifi\"} 5000"
+        ));
+        assert!(body.contains("belabot_temperature_celsius{device=\"primary\"} 45.5"));
+        assert!(body.contains("belabot_streaming{device=\"primary\"} 1"));
+        assert!(body.contains("belabot_belabox_reconnects_total{device=\"primary\"} 2"));
+        assert!(body.contains("belabot_commands_total{command=\"Stats\"} 3"));
+        assert!(body.ends_with("# EOF\n"));
+    }
+}
@@ -1,46 +1,158 @@
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+    time::Duration,
+};
 
 use tokio::{
-    sync::{broadcast, RwLock},
+    sync::{broadcast, watch, Mutex, RwLock},
     time::Instant,
 };
-use tracing::{error, warn};
+use tracing::{error, info, warn};
 
 use crate::{
     belabox::{self, messages, Message},
-    bot::BelaState,
-    config, Belabox, Twitch,
+    bot::{BelaState, Session},
+    config::{self, NotificationTarget},
+    discord::{self, Discord},
+    obs::Obs,
+    templates::Templates,
+    Belabox, Twitch,
 };
 
+/// The bitrate has to rise this much above the warning threshold before it
+/// counts as recovered, so fluctuations around the threshold don't spam.
+const BITRATE_HYSTERESIS: f64 = 1.2;
+
+/// How many bitrate samples are kept, the BELABOX sends one every second.
+const BITRATE_HISTORY_LEN: usize = 60;
+
+/// The automatic restarts because of a stall are counted again after this
+/// long without one.
+const STALL_RESET: Duration = Duration::from_secs(10 * 60);
+
+/// How long the stream is stopped when it's restarted because of a stall.
+const STALL_RESTART_WAIT: Duration = Duration::from_secs(5);
+
+/// What to do about a bitrate which is stuck at 0 kbps.
+#[derive(Debug, PartialEq, Eq)]
+enum Stall {
+    /// Restart the stream, the number of the attempt
+    Restart(u32),
+    GaveUp,
+}
+
+/// The connections between belabot and the BELABOX.
+#[derive(Debug, Clone, Copy)]
+pub enum Link {
+    /// belabot to BELABOX Cloud
+    Cloud,
+    /// BELABOX Cloud to the BELABOX
+    Encoder,
+}
+
+/// Collapses identical messages sent within a window into one.
+#[derive(Debug, Clone, Default)]
+pub struct Throttle {
+    /// When a message was first sent and how often it repeated since
+    sent: Arc<Mutex<HashMap<String, (Instant, u32)>>>,
+}
+
+impl Throttle {
+    /// Returns `None` if the message can be sent. Otherwise it's counted as a
+    /// repeat, returns when the window ends for the first repeat.
+    async fn repeat(&self, message: &str, window: Duration) -> Option<Option<Instant>> {
+        if window.is_zero() {
+            return None;
+        }
+
+        let now = Instant::now();
+        let mut sent = self.sent.lock().await;
+        // Repeated messages are removed by `finish` after sending the summary
+        sent.retain(|_, (since, repeated)| *repeated > 0 || now.duration_since(*since) < window);
+
+        match sent.get_mut(message) {
+            Some((since, repeated)) => {
+                *repeated += 1;
+                Some((*repeated == 1).then_some(*since + window))
+            }
+            None => {
+                sent.insert(message.to_owned(), (now, 0));
+                None
+            }
+        }
+    }
+
+    /// Ends the window of the message, returns how often it repeated.
+    async fn finish(&self, message: &str) -> u32 {
+        self.sent
+            .lock()
+            .await
+            .remove(message)
+            .map(|(_, repeated)| repeated)
+            .unwrap_or(0)
+    }
+}
+
 pub struct Monitor {
+    /// Put in front of every message, for example `BB`
+    pub prefix: String,
+    /// Restarts the stream when the bitrate stalls
     pub belabox: Arc<Belabox>,
     pub bela_state: Arc<RwLock<BelaState>>,
     pub twitch: Arc<Twitch>,
+    pub discord: Option<Discord>,
+    /// Where the events are sent
+    pub targets: config::NotificationTargets,
+    /// Switches scenes when the bitrate is low
+    pub obs: Option<Obs>,
+    pub templates: Arc<Templates>,
+    pub throttle: Throttle,
+    /// The current monitor settings, they change when the config is reloaded
+    pub settings: watch::Receiver<config::Monitor>,
 }
 
 impl Monitor {
-    pub async fn run(
-        &self,
-        mut messages: broadcast::Receiver<belabox::Message>,
-        monitor: config::Monitor,
-    ) {
+    pub async fn run(&self, mut messages: broadcast::Receiver<belabox::Message>) {
         while let Ok(message) = messages.recv().await {
+            let monitor = self.settings.borrow().clone();
+
             match message {
                 Message::Netif(netif) => {
-                    if monitor.modems {
-                        self.modems(netif).await;
+                    self.bitrate(&netif, &monitor).await;
+                    self.stall(&netif, &monitor).await;
+
+                    if monitor.modems || self.discord.is_some() || self.targets.modems.is_some() {
+                        self.modems(netif, monitor.modems).await;
                     }
                 }
+                Message::StreamingStatus(messages::StreamingStatus { is_streaming })
+                | Message::Status(messages::Status { is_streaming, .. }) => {
+                    self.streaming(is_streaming).await;
+                }
                 Message::Sensors(sensors) => {
+                    if let Some(limit) = monitor.temperature_warn_celsius {
+                        self.temperature(&sensors, limit, monitor.temperature_warn_cooldown_secs)
+                            .await;
+                    }
+
                     if monitor.ups {
                         self.ups(sensors, monitor.ups_plugged_in).await;
                     }
                 }
-                Message::Notification(notification) => {
-                    if monitor.notifications {
-                        self.notifications(notification, monitor.notification_timeout)
-                            .await;
-                    }
+                Message::Connected(connected) if monitor.notifications => {
+                    self.connection(Link::Cloud, connected).await;
+                }
+                Message::RemoteEncoder(remote) if monitor.notifications => {
+                    self.connection(Link::Encoder, remote.is_encoder_online)
+                        .await;
+                }
+                Message::Notification(notification) if monitor.notifications => {
+                    self.notifications(notification, monitor.notification_timeout)
+                        .await;
+                }
+                Message::NotificationRemove(remove) if monitor.notifications => {
+                    self.notifications_resolved(remove).await;
                 }
                 _ => {}
             }
@@ -48,47 +160,414 @@ impl Monitor {
     }
 
     async fn send(&self, message: String) {
+        if self.is_quiet() {
+            info!(message, "Quiet hours, not sending the notification");
+            return;
+        }
+
+        let window = Duration::from_secs(self.settings.borrow().throttle_secs);
+
+        match self.throttle.repeat(&message, window).await {
+            None => {}
+            // Send one summary of the repeats when the window ends
+            Some(Some(window_end)) => {
+                let throttle = self.throttle.clone();
+                let twitch = self.twitch.clone();
+                let templates = self.templates.clone();
+
+                tokio::spawn(async move {
+                    tokio::time::sleep_until(window_end).await;
+
+                    let count = throttle.finish(&message).await;
+                    let summary = templates.render(
+                        "monitor_repeated",
+                        &[("message", &message), ("count", &count)],
+                    );
+
+                    if let Err(e) = twitch.send(summary).await {
+                        error!(?e, "error sending message to twitch");
+                    }
+                });
+
+                return;
+            }
+            Some(None) => return,
+        }
+
         if let Err(e) = self.twitch.send(message).await {
             error!(?e, "error sending message to twitch");
         }
     }
 
-    pub async fn modems(&self, netif: HashMap<String, messages::Netif>) {
-        let read = self.bela_state.read().await;
-        let previous = match &read.netif {
-            Some(p) => p,
+    fn discord(&self, event: discord::Event) {
+        if self.is_quiet() {
+            info!(?event, "Quiet hours, not sending the Discord notification");
+            return;
+        }
+
+        if let Some(discord) = &self.discord {
+            discord.send(&self.prefix, event);
+        }
+    }
+
+    /// Sends the message of an event to its `targets`. Without targets it's
+    /// sent to chat when `chat` is set, and the Discord `event` to Discord.
+    async fn notify(
+        &self,
+        targets: &Option<BTreeSet<NotificationTarget>>,
+        chat: bool,
+        event: Option<discord::Event>,
+        message: String,
+    ) {
+        let (chat, discord, log) = match targets {
+            Some(t) => (
+                t.contains(&NotificationTarget::Chat),
+                t.contains(&NotificationTarget::Discord),
+                t.contains(&NotificationTarget::Log),
+            ),
+            None => (chat, true, false),
+        };
+
+        if log {
+            info!(device = self.prefix, message, "Notification");
+        }
+
+        if let (true, Some(event)) = (discord, event) {
+            self.discord(event);
+        }
+
+        if chat {
+            self.send(format!("{}: {}", self.prefix, message)).await;
+        }
+    }
+
+    fn is_quiet(&self) -> bool {
+        self.settings
+            .borrow()
+            .quiet_hours
+            .is_quiet(chrono::Utc::now())
+    }
+
+    /// Notifies when a connection goes down or recovers.
+    pub async fn connection(&self, link: Link, up: bool) {
+        let changed = {
+            let mut lock = self.bela_state.write().await;
+            let previous = match link {
+                Link::Cloud => lock.notify_connected.replace(up),
+                Link::Encoder => lock.notify_encoder_online.replace(up),
+            };
+
+            // Don't notify about the state when starting the bot
+            previous.is_some() && previous != Some(up)
+        };
+
+        if !changed {
+            return;
+        }
+
+        let key = match (link, up) {
+            (Link::Cloud, false) => "cloud_disconnected",
+            (Link::Cloud, true) => "cloud_reconnected",
+            (Link::Encoder, false) => "encoder_offline",
+            (Link::Encoder, true) => "encoder_online",
+        };
+
+        let message = self.templates.get(key);
+
+        if up {
+            self.discord(discord::Event::Reconnected(message.to_owned()));
+        } else {
+            warn!(?link, "connection lost");
+            self.discord(discord::Event::Disconnected(message.to_owned()));
+        }
+
+        self.send(format!("{}: {}", self.prefix, message)).await;
+    }
+
+    /// Notifies when the stream starts or stops.
+    pub async fn streaming(&self, is_streaming: bool) {
+        let changed = {
+            let mut lock = self.bela_state.write().await;
+            let previous = lock.notify_streaming.replace(is_streaming);
+
+            // The start time is only approximate when the bot started during
+            // the stream
+            if !is_streaming {
+                lock.session = None;
+            } else if lock.session.is_none() {
+                lock.session = Some(Session::new());
+            }
+
+            // Don't notify about the state when starting the bot
+            previous.is_some() && previous != Some(is_streaming)
+        };
+
+        if !changed {
+            return;
+        }
+
+        let (event, key) = if is_streaming {
+            (discord::Event::StreamStarted, "stream_started")
+        } else {
+            (discord::Event::StreamStopped, "stream_stopped")
+        };
+
+        let message = self.templates.get(key);
+        self.notify(&self.targets.stream, false, Some(event), message)
+            .await;
+    }
+
+    /// Notifies when the total bitrate stays too low while streaming.
+    pub async fn bitrate(
+        &self,
+        netif: &HashMap<String, messages::Netif>,
+        monitor: &config::Monitor,
+    ) {
+        let total = netif
+            .values()
+            .filter(|i| i.enabled)
+            .map(|i| (i.tp * 8) / 1024)
+            .sum::<u64>();
+
+        let warn_kbps = monitor.bitrate_warn_kbps;
+        let recover_kbps = (warn_kbps as f64 * BITRATE_HYSTERESIS) as u64;
+
+        // Some(true) when the bitrate dropped, Some(false) when it recovered
+        let change = {
+            let mut lock = self.bela_state.write().await;
+            let is_streaming = lock.is_streaming;
+            let now = Instant::now();
+
+            if let (true, Some(session)) = (is_streaming, &mut lock.session) {
+                session.add_bitrate(total);
+            }
+
+            if lock.bitrate_history.len() >= BITRATE_HISTORY_LEN {
+                lock.bitrate_history.pop_front();
+            }
+            lock.bitrate_history.push_back(total);
+
+            if is_streaming && total < warn_kbps {
+                let since = *lock.bitrate_low_since.get_or_insert(now);
+                let low_for = now.duration_since(since);
+
+                if !lock.notify_bitrate_low
+                    && low_for >= Duration::from_secs(monitor.bitrate_warn_secs)
+                {
+                    lock.notify_bitrate_low = true;
+                    Some(true)
+                } else {
+                    None
+                }
+            } else {
+                lock.bitrate_low_since = None;
+
+                if lock.notify_bitrate_low && (!is_streaming || total >= recover_kbps) {
+                    lock.notify_bitrate_low = false;
+
+                    // Stopping the stream is not a recovery
+                    is_streaming.then_some(false)
+                } else {
+                    None
+                }
+            }
+        };
+
+        match change {
+            Some(true) => {
+                if let Some(obs) = &self.obs {
+                    obs.bitrate_low();
+                }
+
+                let msg = self.templates.render("bitrate_low", &[("bitrate", &total)]);
+                let event = discord::Event::BitrateLow(total);
+                self.notify(&self.targets.bitrate, monitor.bitrate, Some(event), msg)
+                    .await;
+            }
+            Some(false) => {
+                if let Some(obs) = &self.obs {
+                    obs.bitrate_recovered();
+                }
+
+                let msg = self
+                    .templates
+                    .render("bitrate_recovered", &[("bitrate", &total)]);
+                self.notify(&self.targets.bitrate, monitor.bitrate, None, msg)
+                    .await;
+            }
+            None => {}
+        }
+    }
+
+    /// Restarts the stream when the bitrate stays at 0 kbps, up to
+    /// `auto_restart_max_attempts` times.
+    pub async fn stall(&self, netif: &HashMap<String, messages::Netif>, monitor: &config::Monitor) {
+        let secs = match monitor.auto_restart_on_stall_secs {
+            Some(s) => s,
             None => return,
         };
 
-        let added = netif
-            .keys()
-            .filter(|&n| !previous.contains_key(n))
-            .map(|n| n.to_owned())
-            .collect::<Vec<String>>();
+        let total = netif
+            .values()
+            .filter(|i| i.enabled)
+            .map(|i| i.tp)
+            .sum::<u64>();
+
+        let stall = {
+            let mut lock = self.bela_state.write().await;
+            let now = Instant::now();
+
+            // A reboot from `Restart` stalls as well
+            if !lock.is_streaming || total > 0 || lock.resuming.is_some() {
+                lock.stalled_since = None;
+                return;
+            }
 
-        let removed = previous
-            .keys()
-            .filter(|&n| !netif.contains_key(n))
-            .map(|n| n.to_owned())
-            .collect::<Vec<String>>();
+            let since = *lock.stalled_since.get_or_insert(now);
+
+            if now.duration_since(since) < Duration::from_secs(secs) {
+                return;
+            }
+
+            lock.stalled_since = None;
+
+            let reset = lock
+                .stall_restarted
+                .map(|t| now.duration_since(t) >= STALL_RESET)
+                .unwrap_or(true);
+
+            if reset {
+                lock.stall_restarts = 0;
+                lock.stall_gave_up = false;
+            }
+
+            if lock.stall_restarts < monitor.auto_restart_max_attempts {
+                lock.stall_restarts += 1;
+                lock.stall_restarted = Some(now);
+                Stall::Restart(lock.stall_restarts)
+            } else if !lock.stall_gave_up {
+                lock.stall_gave_up = true;
+                Stall::GaveUp
+            } else {
+                return;
+            }
+        };
+
+        let max = monitor.auto_restart_max_attempts;
+        let msg = match stall {
+            Stall::Restart(attempt) => {
+                warn!(
+                    attempt,
+                    "the bitrate is stuck at 0 kbps, restarting the stream"
+                );
+
+                let belabox = self.belabox.clone();
+                let bela_state = self.bela_state.clone();
+                tokio::spawn(restart_stream(belabox, bela_state));
+
+                self.templates
+                    .render("stall_restart", &[("attempt", &attempt), ("max", &max)])
+            }
+            Stall::GaveUp => {
+                warn!("the bitrate is still stuck at 0 kbps, not restarting again");
+                self.templates.render("stall_gave_up", &[("max", &max)])
+            }
+        };
+
+        self.send(format!("{}: {}", self.prefix, msg)).await;
+    }
+
+    /// Notifies about connected and disconnected modems, `chat` sends the
+    /// message to Twitch chat as well.
+    pub async fn modems(&self, netif: HashMap<String, messages::Netif>, chat: bool) {
+        // The lock isn't held while sending
+        let (added, removed) = {
+            let read = self.bela_state.read().await;
+            let previous = match &read.netif {
+                Some(p) => p,
+                None => return,
+            };
+
+            let added = netif
+                .keys()
+                .filter(|&n| !previous.contains_key(n))
+                .map(|n| n.to_owned())
+                .collect::<Vec<String>>();
+
+            let removed = previous
+                .keys()
+                .filter(|&n| !netif.contains_key(n))
+                .map(|n| n.to_owned())
+                .collect::<Vec<String>>();
+
+            (added, removed)
+        };
 
         let mut message = Vec::new();
 
         if !added.is_empty() {
-            let a = if added.len() > 1 { "are" } else { "is" };
+            let key = if added.len() > 1 {
+                "modems_connected"
+            } else {
+                "modem_connected"
+            };
 
-            message.push(format!("{} {} now connected", added.join(", "), a));
+            let interfaces = added.join(", ");
+            message.push(self.templates.render(key, &[("interfaces", &interfaces)]));
         }
 
         if !removed.is_empty() {
-            let a = if removed.len() > 1 { "have" } else { "has" };
+            let key = if removed.len() > 1 {
+                "modems_disconnected"
+            } else {
+                "modem_disconnected"
+            };
 
-            message.push(format!("{} {} disconnected", removed.join(", "), a));
+            let interfaces = removed.join(", ");
+            message.push(self.templates.render(key, &[("interfaces", &interfaces)]));
         }
 
-        if !message.is_empty() {
-            self.send(format!("BB: {}", message.join(", "))).await;
+        if message.is_empty() {
+            return;
         }
+
+        let message = message.join(", ");
+        let event = discord::Event::Modems(message.to_owned());
+        self.notify(&self.targets.modems, chat, Some(event), message)
+            .await;
+    }
+
+    /// Warns when the SoC temperature is above the limit.
+    pub async fn temperature(&self, sensors: &messages::Sensors, limit: f64, cooldown: u64) {
+        let temperature = match sensors.soc_temperature_celsius() {
+            Some(t) => t,
+            None => return,
+        };
+
+        if temperature <= limit {
+            return;
+        }
+
+        {
+            let mut lock = self.bela_state.write().await;
+
+            if let Some(time) = lock.temperature_warned {
+                if time.elapsed() < Duration::from_secs(cooldown) {
+                    return;
+                }
+            }
+
+            lock.temperature_warned = Some(Instant::now());
+        }
+
+        warn!(temperature, "high temperature");
+
+        let msg = self
+            .templates
+            .render("temperature_high", &[("temperature", &temperature)]);
+        let event = discord::Event::Temperature(temperature);
+        self.notify(&self.targets.temperature, true, Some(event), msg)
+            .await;
     }
 
     pub async fn ups(&self, sensors: messages::Sensors, plugged_voltage: f64) {
@@ -122,10 +601,14 @@ impl Monitor {
         };
 
         if let Some(c) = charging {
-            let a = if !c { "not" } else { "" };
-            let msg = format!("BB: UPS {} charging", a);
+            let ups = if c {
+                self.templates.get("ups_charging")
+            } else {
+                self.templates.get("ups_not_charging")
+            };
 
-            self.send(msg).await;
+            let msg = self.templates.render("monitor_ups", &[("ups", &ups)]);
+            self.send(format!("{}: {}", self.prefix, msg)).await;
         }
     }
 
@@ -134,25 +617,164 @@ impl Monitor {
         notification: messages::Notification,
         notification_timeout: u64,
     ) {
-        let mut lock = self.bela_state.write().await;
-        let timeout = &mut lock.notification_timeout;
+        // The lock isn't held while sending
+        let messages = {
+            let mut lock = self.bela_state.write().await;
+            let now = Instant::now();
+            let mut messages = Vec::new();
 
-        let now = Instant::now();
-        for notification in notification.show {
-            if let Some(time) = timeout.get(&notification.name) {
-                if time.elapsed() < Duration::from_secs(notification_timeout) {
-                    continue;
+            for notification in notification.show {
+                if let Some(time) = lock.notification_timeout.get(&notification.name) {
+                    if time.elapsed() < Duration::from_secs(notification_timeout) {
+                        continue;
+                    }
                 }
+
+                warn!(notification.msg, "notication");
+
+                lock.notification_timeout
+                    .entry(notification.name.to_owned())
+                    .and_modify(|n| *n = now)
+                    .or_insert(now);
+
+                lock.active_notifications
+                    .insert(notification.name, notification.msg.to_owned());
+
+                messages.push(notification.msg);
             }
 
-            warn!(notification.msg, "notication");
+            messages
+        };
+
+        for msg in messages {
+            self.send(format!("{}: {}", self.prefix, msg)).await;
+        }
+    }
+
+    /// Tells chat when a notification it was told about is resolved.
+    pub async fn notifications_resolved(&self, remove: messages::NotificationRemove) {
+        let resolved = {
+            let mut lock = self.bela_state.write().await;
+
+            remove
+                .remove
+                .iter()
+                .filter_map(|name| lock.active_notifications.remove(name))
+                .collect::<Vec<String>>()
+        };
+
+        for notification in resolved {
+            let msg = self
+                .templates
+                .render("notification_resolved", &[("notification", &notification)]);
+            self.send(format!("{}: {}", self.prefix, msg)).await;
+        }
+    }
+}
+
+/// Stops the stream and starts it again with the same settings.
+async fn restart_stream(belabox: Arc<Belabox>, bela_state: Arc<RwLock<BelaState>>) {
+    if let Err(e) = belabox.stop().await {
+        error!(?e, "failed to stop the stalled stream");
+        return;
+    }
 
-            timeout
-                .entry(notification.name)
-                .and_modify(|n| *n = now)
-                .or_insert(now);
+    tokio::time::sleep(STALL_RESTART_WAIT).await;
 
-            self.send("BB: ".to_owned() + &notification.msg).await;
+    let config = bela_state.read().await.config.to_owned();
+
+    let config = match config {
+        Some(c) => c,
+        None => {
+            error!("no BELABOX config to start the stream again");
+            return;
         }
+    };
+
+    if let Err(e) = belabox.start(belabox::requests::Start::from(config)).await {
+        error!(?e, "failed to start the stream again");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::belabox::mock::MockBelabox;
+
+    #[tokio::test]
+    async fn stall_restarts() {
+        let mock = MockBelabox::start(Vec::new()).await.unwrap();
+        let monitor = config::Monitor {
+            auto_restart_on_stall_secs: Some(0),
+            auto_restart_max_attempts: 1,
+            ..Default::default()
+        };
+        let handler = Monitor {
+            prefix: "BB".to_owned(),
+            belabox: Arc::new(mock.connect().await),
+            bela_state: Arc::new(RwLock::new(BelaState::default())),
+            twitch: Arc::new(Twitch::offline(vec!["715209".to_owned()])),
+            discord: None,
+            targets: Default::default(),
+            obs: None,
+            templates: Arc::new(Templates::new(Default::default())),
+            throttle: Throttle::default(),
+            settings: watch::channel(monitor.to_owned()).1,
+        };
+        handler.bela_state.write().await.is_streaming = true;
+
+        let netif = |tp| {
+            HashMap::from([(
+                "usb0".to_owned(),
+                messages::Netif {
+                    ip: "10.0.0.2".to_owned(),
+                    txb: 0,
+                    tp,
+                    enabled: true,
+                },
+            )])
+        };
+
+        handler.stall(&netif(100), &monitor).await;
+        assert_eq!(handler.bela_state.read().await.stall_restarts, 0);
+
+        handler.stall(&netif(0), &monitor).await;
+        assert_eq!(mock.request().await, Some(serde_json::json!({ "stop": 0 })));
+        assert_eq!(handler.bela_state.read().await.stall_restarts, 1);
+
+        // The limit is reached, so no more restarts
+        handler.stall(&netif(0), &monitor).await;
+        assert!(handler.bela_state.read().await.stall_gave_up);
+        assert_eq!(mock.request().await, None);
+    }
+
+    #[tokio::test]
+    async fn throttle_repeats() {
+        let throttle = Throttle::default();
+        let window = Duration::from_secs(60);
+
+        assert_eq!(throttle.repeat("usb0 disconnected", window).await, None);
+        assert!(matches!(
+            throttle.repeat("usb0 disconnected", window).await,
+            Some(Some(_))
+        ));
+        assert_eq!(
+            throttle.repeat("usb0 disconnected", window).await,
+            Some(None)
+        );
+        assert_eq!(throttle.repeat("usb0 connected", window).await, None);
+
+        assert_eq!(throttle.finish("usb0 disconnected").await, 2);
+        assert_eq!(throttle.repeat("usb0 disconnected", window).await, None);
+
+        let disabled = Throttle::default();
+        assert_eq!(
+            disabled.repeat("usb0 disconnected", Duration::ZERO).await,
+            None
+        );
+        assert_eq!(
+            disabled.repeat("usb0 disconnected", Duration::ZERO).await,
+            None
+        );
     }
 }
@@ -0,0 +1,274 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::{
+    net::TcpStream,
+    sync::mpsc,
+    time::{self, Duration},
+};
+use tokio_tungstenite::{tungstenite, MaybeTlsStream, WebSocketStream};
+use tracing::{debug, error, info};
+
+use crate::config;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// The OBS WebSocket version 5 RPC version.
+const RPC_VERSION: u32 = 1;
+
+/// Op codes of the OBS WebSocket protocol.
+const OP_HELLO: u64 = 0;
+const OP_IDENTIFY: u64 = 1;
+const OP_IDENTIFIED: u64 = 2;
+const OP_REQUEST: u64 = 6;
+const OP_REQUEST_RESPONSE: u64 = 7;
+
+/// How long to wait for connecting and for each response of OBS.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Error, Debug)]
+pub enum ObsError {
+    #[error("websocket error")]
+    Websocket(#[from] Box<tungstenite::Error>),
+    #[error("json error")]
+    Json(#[from] serde_json::Error),
+    #[error("the connection was closed")]
+    Closed,
+    #[error("OBS asks for a password but none is set")]
+    MissingPassword,
+    #[error("request {0} failed: {1}")]
+    Request(String, String),
+    #[error("OBS didn't respond in time")]
+    Timeout,
+}
+
+#[derive(Debug)]
+enum Switch {
+    Low,
+    Recovered,
+}
+
+/// Switches OBS scenes over OBS WebSocket version 5.
+#[derive(Debug, Clone)]
+pub struct Obs {
+    switches: mpsc::UnboundedSender<Switch>,
+}
+
+impl Obs {
+    /// Starts the task which switches the scenes, one after the other so a
+    /// recovery can't overtake the switch to the low bitrate scene.
+    pub fn new(config: config::Obs) -> Self {
+        let (switches, rx) = mpsc::unbounded_channel();
+
+        let scenes = Scenes {
+            config,
+            previous_scene: None,
+        };
+        tokio::spawn(scenes.run(rx));
+
+        Self { switches }
+    }
+
+    /// Switches to the low bitrate scene in the background, failures are
+    /// only logged.
+    pub fn bitrate_low(&self) {
+        let _ = self.switches.send(Switch::Low);
+    }
+
+    /// Switches back to the scene from before the bitrate was low in the
+    /// background.
+    pub fn bitrate_recovered(&self) {
+        let _ = self.switches.send(Switch::Recovered);
+    }
+}
+
+struct Scenes {
+    config: config::Obs,
+    /// The scene before switching to the low bitrate scene
+    previous_scene: Option<String>,
+}
+
+impl Scenes {
+    async fn run(mut self, mut switches: mpsc::UnboundedReceiver<Switch>) {
+        while let Some(switch) = switches.recv().await {
+            let res = match switch {
+                Switch::Low => self.switch_to_low_scene().await,
+                Switch::Recovered => self.switch_back().await,
+            };
+
+            if let Err(e) = res {
+                error!(?e, ?switch, "error switching the OBS scene");
+            }
+        }
+    }
+
+    async fn switch_to_low_scene(&mut self) -> Result<(), ObsError> {
+        let mut socket = self.connect().await?;
+
+        let current = request(&mut socket, "GetCurrentProgramScene", json!({})).await?;
+        let current = current["currentProgramSceneName"]
+            .as_str()
+            .map(|s| s.to_owned());
+
+        // Already switched by someone else
+        if current.as_deref() == Some(self.config.low_bitrate_scene.as_str()) {
+            return Ok(());
+        }
+
+        self.previous_scene = current;
+
+        set_scene(&mut socket, &self.config.low_bitrate_scene).await?;
+        info!(
+            scene = self.config.low_bitrate_scene,
+            "switched the OBS scene"
+        );
+
+        let _ = socket.close(None).await;
+
+        Ok(())
+    }
+
+    async fn switch_back(&mut self) -> Result<(), ObsError> {
+        let previous = self.previous_scene.take();
+
+        let scene = match self.config.live_scene.to_owned().or(previous) {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+
+        let mut socket = self.connect().await?;
+        set_scene(&mut socket, &scene).await?;
+        info!(scene, "switched the OBS scene");
+
+        let _ = socket.close(None).await;
+
+        Ok(())
+    }
+
+    /// Connects and identifies, with a password when OBS asks for one.
+    async fn connect(&self) -> Result<Socket, ObsError> {
+        let url = format!("ws://{}:{}", self.config.host, self.config.port);
+        debug!(url, "connecting to OBS");
+
+        let (mut socket, _) = time::timeout(TIMEOUT, tokio_tungstenite::connect_async(url))
+            .await
+            .map_err(|_| ObsError::Timeout)?
+            .map_err(Box::new)?;
+
+        let hello = receive(&mut socket, OP_HELLO).await?;
+
+        let mut identify = json!({ "rpcVersion": RPC_VERSION });
+
+        if let Some(auth) = hello.get("authentication") {
+            let password = self
+                .config
+                .password
+                .as_deref()
+                .ok_or(ObsError::MissingPassword)?;
+            let challenge = auth["challenge"].as_str().unwrap_or_default();
+            let salt = auth["salt"].as_str().unwrap_or_default();
+
+            identify["authentication"] = authentication(password, salt, challenge).into();
+        }
+
+        send(&mut socket, OP_IDENTIFY, identify).await?;
+        receive(&mut socket, OP_IDENTIFIED).await?;
+
+        Ok(socket)
+    }
+}
+
+/// The authentication string of OBS WebSocket version 5.
+fn authentication(password: &str, salt: &str, challenge: &str) -> String {
+    let secret = STANDARD.encode(Sha256::digest(format!("{}{}", password, salt)));
+
+    STANDARD.encode(Sha256::digest(format!("{}{}", secret, challenge)))
+}
+
+async fn set_scene(socket: &mut Socket, scene: &str) -> Result<(), ObsError> {
+    request(
+        socket,
+        "SetCurrentProgramScene",
+        json!({ "sceneName": scene }),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Sends a request and returns the data of the response.
+async fn request(socket: &mut Socket, kind: &str, data: Value) -> Result<Value, ObsError> {
+    let request = json!({
+        "requestType": kind,
+        "requestId": kind,
+        "requestData": data,
+    });
+
+    send(socket, OP_REQUEST, request).await?;
+
+    let response = receive(socket, OP_REQUEST_RESPONSE).await?;
+    let status = &response["requestStatus"];
+
+    if status["result"].as_bool() != Some(true) {
+        let comment = status["comment"].as_str().unwrap_or_default().to_owned();
+        return Err(ObsError::Request(kind.to_owned(), comment));
+    }
+
+    Ok(response["responseData"].to_owned())
+}
+
+async fn send(socket: &mut Socket, op: u64, data: Value) -> Result<(), ObsError> {
+    let message = json!({ "op": op, "d": data }).to_string();
+
+    socket
+        .send(tungstenite::Message::Text(message))
+        .await
+        .map_err(Box::new)?;
+
+    Ok(())
+}
+
+/// Waits for a message with the op code, other messages like events are
+/// skipped.
+async fn receive(socket: &mut Socket, op: u64) -> Result<Value, ObsError> {
+    time::timeout(TIMEOUT, wait_for(socket, op))
+        .await
+        .map_err(|_| ObsError::Timeout)?
+}
+
+async fn wait_for(socket: &mut Socket, op: u64) -> Result<Value, ObsError> {
+    while let Some(message) = socket.next().await {
+        let text = match message.map_err(Box::new)? {
+            tungstenite::Message::Text(t) => t,
+            tungstenite::Message::Close(_) => return Err(ObsError::Closed),
+            _ => continue,
+        };
+
+        let mut message: Value = serde_json::from_str(&text)?;
+
+        if message["op"].as_u64() == Some(op) {
+            return Ok(message["d"].take());
+        }
+    }
+
+    Err(ObsError::Closed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn authentication_string() {
+        // Example from the OBS WebSocket protocol documentation
+        let auth = authentication(
+            "supersecretpassword",
+            "lM1GncleQOaCu9lT1yeUZhFYnqhsLLP1G5lAGo3ixaI=",
+            "+IxH4CnCiqpX1rM9scsNynZzbOe4KhDeYcTNS3PDaeY=",
+        );
+
+        assert_eq!(auth, "1Ct943GAT+6YQUUX47Ia/ncufilbe6+oD6lY+5kaCu4=");
+    }
+}
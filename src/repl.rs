@@ -0,0 +1,161 @@
+//! Simulated chat, lines typed on stdin are handled as chat messages by the
+//! commands of the config, against a mock BELABOX instead of a real one.
+
+use std::{path::Path, sync::Arc};
+
+use serde_json::json;
+use tokio::{
+    io::{self, AsyncBufReadExt, BufReader},
+    time::{self, Duration},
+};
+use tracing::warn;
+
+use crate::{
+    belabox::mock::MockBelabox, config, error::Error, twitch::HandleMessage, Bot, Settings, Twitch,
+};
+
+/// What the mock BELABOX sends after logging in, an idle encoder.
+const SCRIPT: [&str; 6] = [
+    r#"{"remote":{"is_encoder_online":true,"version":13}}"#,
+    r#"{"config":{"password_hash":"","remote_key":"repl","max_br":6000,"delay":0,"pipeline":"h265_camlink_1080p","srt_latency":2000,"srt_streamid":"","srtla_addr":"","srtla_port":"5000","bitrate_overlay":false,"ssh_pass":null,"asrc":"USB audio","acodec":"opus"}}"#,
+    r#"{"netif":{"wlan0":{"ip":"192.168.1.2","txb":0,"tp":0,"enabled":true},"usb0":{"ip":"10.0.0.2","txb":0,"tp":0,"enabled":true}}}"#,
+    r#"{"sensors":{"SoC temperature":"45.5 °C"}}"#,
+    r#"{"status":{"asrcs":["USB audio","No audio"]}}"#,
+    r#"{"status":{"is_streaming":false}}"#,
+];
+
+const HELP: &str = "Type chat messages, or:
+  /as broadcaster|moderator|vip|subscriber|viewer  the role of the sender
+  /user <name>                                     the name of the sender
+  /belabox <json>                                  a message from the BELABOX
+  /quit";
+
+/// The sender of the simulated messages.
+struct Sender {
+    name: String,
+    role: String,
+}
+
+/// Runs the commands of the config at `path` on the lines of stdin until it
+/// closes or `/quit` is typed. The default commands are used when there is
+/// no config, the file is never changed.
+pub async fn run(path: &Path) -> Result<(), Error> {
+    let mut config = match Settings::read(path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!(?e, path = %path.display(), "failed to load the config, using the default commands");
+            let mut config = Settings::default();
+            config::default_chat_commands(&mut config.commands, config::DEFAULT_COMMAND_PREFIX);
+            config
+        }
+    };
+
+    let mock = Arc::new(MockBelabox::start(SCRIPT.iter().map(|m| m.to_string()).collect()).await?);
+
+    // Nothing may reach the real services
+    config.belabox.cloud_url = Some(mock.url.to_owned());
+    for device in config.belabox.devices.values_mut() {
+        device.cloud_url = Some(mock.url.to_owned());
+    }
+    config.obs.enabled = false;
+    config.notifications.discord_webhook_url = None;
+    config.status_server.enabled = false;
+    config.metrics.enabled = false;
+    config.usage.file = None;
+    config.safety.cooldown_file = None;
+    config.dry_run = false;
+
+    // Commands such as `Label` would save to the config
+    config.path = None;
+
+    let channel = config
+        .twitch
+        .channels
+        .first()
+        .map(|c| c.to_lowercase())
+        .unwrap_or_else(|| "belabot".to_owned());
+    let twitch = Twitch::offline(vec![channel.to_owned()]);
+    let bot = Bot::with_twitch(config, twitch).await?;
+
+    let requests = {
+        let mock = mock.clone();
+
+        tokio::spawn(async move {
+            loop {
+                if let Some(request) = mock.request().await {
+                    println!("[BELABOX] {}", request);
+                    reply(&mock, &request);
+                }
+            }
+        })
+    };
+
+    let mut sender = Sender {
+        name: channel.to_owned(),
+        role: "broadcaster".to_owned(),
+    };
+
+    // The state of the mock arrives right after logging in
+    time::sleep(Duration::from_millis(500)).await;
+    println!("{}", HELP);
+
+    let mut lines = BufReader::new(io::stdin()).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(command) = line.strip_prefix('/') {
+            let (command, argument) = command.split_once(' ').unwrap_or((command, ""));
+
+            match (command, argument.trim()) {
+                ("quit", _) => break,
+                ("as", role @ ("broadcaster" | "moderator" | "vip" | "subscriber" | "viewer")) => {
+                    sender.role = role.to_owned()
+                }
+                ("user", name) if !name.is_empty() => sender.name = name.to_lowercase(),
+                ("belabox", message) if !message.is_empty() => mock.send(message),
+                _ => println!("{}", HELP),
+            }
+
+            continue;
+        }
+
+        let message = HandleMessage {
+            channel_name: channel.to_owned(),
+            sender_name: sender.name.to_owned(),
+            sender_id: sender.name.to_owned(),
+            broadcaster: sender.role == "broadcaster",
+            moderator: sender.role == "moderator",
+            vip: sender.role == "vip",
+            subscriber: sender.role == "subscriber",
+            message: line.to_owned(),
+            reward: None,
+        };
+
+        bot.twitch.simulate(message)?;
+    }
+
+    requests.abort();
+    bot.shutdown().await;
+
+    Ok(())
+}
+
+/// Updates the state of the mock like a BELABOX would after the request.
+fn reply(mock: &MockBelabox, request: &serde_json::Value) {
+    let message = if request.get("start").is_some() {
+        json!({ "status": { "is_streaming": true } })
+    } else if request.get("stop").is_some() {
+        json!({ "status": { "is_streaming": false } })
+    } else if let Some(bitrate) = request.get("bitrate") {
+        json!({ "bitrate": bitrate })
+    } else {
+        return;
+    };
+
+    mock.send(&message.to_string());
+}
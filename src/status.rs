@@ -0,0 +1,148 @@
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde::Serialize;
+use tracing::{error, info};
+
+use crate::{command_handler::Device, error::Error};
+
+/// The status of a single BELABOX.
+#[derive(Debug, Serialize)]
+pub(crate) struct DeviceStatus {
+    /// `None` for the primary device
+    pub(crate) name: Option<String>,
+    pub(crate) online: bool,
+    pub(crate) is_streaming: bool,
+    /// Total bitrate of the enabled modems in kbps
+    pub(crate) bitrate_kbps: u64,
+    pub(crate) temperature_celsius: Option<f64>,
+    pub(crate) modems: Vec<ModemStatus>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ModemStatus {
+    /// The custom interface name if there is one
+    pub(crate) name: String,
+    pub(crate) interface: String,
+    pub(crate) ip: String,
+    pub(crate) enabled: bool,
+    pub(crate) bitrate_kbps: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct Status {
+    devices: Vec<DeviceStatus>,
+}
+
+/// Binds the status server and serves it in the background.
+pub async fn spawn(address: SocketAddr, devices: Vec<Device>) -> Result<(), Error> {
+    let devices = Arc::new(devices);
+
+    let make_service = make_service_fn(move |_| {
+        let devices = devices.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let devices = devices.clone();
+                async move { Ok::<_, Infallible>(handle(request, &devices).await) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&address)
+        .map_err(|source| Error::Listen { address, source })?
+        .serve(make_service);
+    info!(%address, "Status server listening");
+
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error!(?e, "status server error");
+        }
+    });
+
+    Ok(())
+}
+
+async fn handle(request: Request<Body>, devices: &[Device]) -> Response<Body> {
+    if request.method() != Method::GET {
+        return empty(StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    if !matches!(request.uri().path(), "/" | "/status") {
+        return empty(StatusCode::NOT_FOUND);
+    }
+
+    let mut status = Status {
+        devices: Vec::with_capacity(devices.len()),
+    };
+
+    for device in devices {
+        status.devices.push(device_status(device).await);
+    }
+
+    let body = match serde_json::to_vec(&status) {
+        Ok(b) => b,
+        Err(e) => {
+            error!(?e, "error serializing status");
+            return empty(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let mut response = Response::new(Body::from(body));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/json"),
+    );
+    response.headers_mut().insert(
+        header::ACCESS_CONTROL_ALLOW_ORIGIN,
+        header::HeaderValue::from_static("*"),
+    );
+
+    response
+}
+
+pub(crate) async fn device_status(device: &Device) -> DeviceStatus {
+    let read = device.bela_state.read().await;
+
+    let mut modems = read
+        .netif
+        .iter()
+        .flatten()
+        .map(|(interface, netif)| ModemStatus {
+            name: device.interface_name(interface, netif),
+            interface: interface.to_owned(),
+            ip: netif.ip.to_owned(),
+            enabled: netif.enabled,
+            bitrate_kbps: if netif.enabled {
+                (netif.tp * 8) / 1024
+            } else {
+                0
+            },
+        })
+        .collect::<Vec<ModemStatus>>();
+
+    // Sort interfaces because they like to move around
+    modems.sort_by(|a, b| a.name.cmp(&b.name));
+
+    DeviceStatus {
+        name: device.name.to_owned(),
+        online: read.online && read.connected,
+        is_streaming: read.is_streaming,
+        bitrate_kbps: modems.iter().map(|m| m.bitrate_kbps).sum(),
+        temperature_celsius: read
+            .sensors
+            .as_ref()
+            .and_then(|s| s.soc_temperature_celsius()),
+        modems,
+    }
+}
+
+fn empty(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
@@ -0,0 +1,292 @@
+use std::{collections::BTreeMap, fmt::Display, fmt::Write as _, sync::RwLock};
+
+/// The built-in chat messages, placeholders like `{bitrate}` are replaced
+/// when the message is rendered.
+pub const DEFAULT_TEMPLATES: &[(&str, &str)] = &[
+    ("offline", "Offline :("),
+    ("disconnected", "Not connected to BELABOX Cloud"),
+    ("stale", "No data from the BELABOX, it might be offline"),
+    ("error", "Error {error}"),
+    ("device_error", "BELABOX error: {error}"),
+    ("permission_denied", "You are not allowed to use {command}"),
+    ("dry_run", "[dry run] {message}"),
+    ("help", "Commands: {commands}"),
+    ("top", "Top commands: {commands}, Top users: {users}"),
+    ("top_empty", "No commands used yet"),
+    ("reload_done", "Reloaded the config"),
+    ("reload_failed", "Failed to reload the config: {error}"),
+    ("reload_no_config", "There is no config file to reload"),
+    (
+        "unknown_command",
+        "Unknown command {command}, {help} lists the commands",
+    ),
+    ("command_suggestion", "Did you mean {command}?"),
+    ("read_only", "The bot is in read-only mode"),
+    (
+        "read_only_enabled",
+        "Read-only mode enabled, only commands that show the status work",
+    ),
+    ("read_only_disabled", "Read-only mode disabled"),
+    ("read_only_invalid", "Use on or off"),
+    ("monitor_repeated", "{message} (repeated {count} times)"),
+    ("notification_resolved", "Resolved: {notification}"),
+    (
+        "bot_uptime",
+        "belabot {version} has been running for {uptime}",
+    ),
+    ("version", "belabot {version} ({commit})"),
+    (
+        "stall_restart",
+        "The bitrate is stuck at 0 kbps, restarting the stream ({attempt}/{max})",
+    ),
+    (
+        "stall_gave_up",
+        "The bitrate is still stuck at 0 kbps after {max} restarts, not restarting again",
+    ),
+    (
+        "version_update",
+        "belabot {version} ({commit}), {latest} is available",
+    ),
+    ("unknown", "unknown"),
+    ("invalid_number", "Invalid number {value} given"),
+    ("usage", "{message}, usage: {usage}"),
+    (
+        "invalid_range",
+        "Invalid value: {value}, use a value between {min} - {max}",
+    ),
+    ("start", "Starting BELABOX"),
+    ("start_relay", "Starting BELABOX on {relay}"),
+    ("relays_unavailable", "Relay servers not available"),
+    ("relay_not_found", "Relay not found, use one of: {relays}"),
+    ("start_error", "Error starting BELABOX"),
+    ("already_streaming", "Error already streaming"),
+    ("stop", "Stopping BELABOX"),
+    ("not_streaming", "Error not streaming"),
+    (
+        "stop_idle",
+        "Stopping BELABOX, it has no idle mode so it stays powered on",
+    ),
+    ("stop_invalid", "Use idle or nothing"),
+    ("stream_restarting", "Restarting the stream"),
+    ("stats", "{interfaces}, Total: {bitrate} kbps"),
+    ("stats_interface", "{interface}: {bitrate} kbps"),
+    ("stats_interface_disabled", "{interface}: disabled"),
+    ("stats_ups", "{stats}, UPS: {ups}"),
+    (
+        "stats_session",
+        "{stats}, Live: {uptime}, Avg: {bitrate} kbps",
+    ),
+    ("history", "Last {secs}s: {graph} Max: {max} kbps"),
+    ("history_unavailable", "No bitrate history yet"),
+    ("ups_charging", "charging"),
+    ("ups_not_charging", "not charging"),
+    ("outputs", "Outputs: {outputs}, current: {current}"),
+    ("outputs_unavailable", "No outputs in the config"),
+    (
+        "output_not_found",
+        "Output {output} not found, outputs: {outputs}",
+    ),
+    ("output_changed", "Switched the output to {output}"),
+    ("restart", "Rebooting BELABOX"),
+    ("restart_done", "Reboot successful, starting the stream"),
+    ("restart_live", "The stream is back online"),
+    (
+        "restart_timeout",
+        "The stream didn't come back within {seconds} seconds after the reboot",
+    ),
+    ("poweroff", "Powering off BELABOX"),
+    ("confirm", "Type {command} within {secs}s to confirm"),
+    ("bitrate_current", "Current max bitrate is {bitrate} kbps"),
+    (
+        "bitrate_adaptive",
+        "The BELABOX always adapts the bitrate to the network, only the max bitrate can be changed",
+    ),
+    ("bitrate_fixed_missing", "No bitrate given after fixed"),
+    ("bitrate_changed", "Changed max bitrate to {bitrate} kbps"),
+    ("interfaces_unavailable", "Interfaces not available"),
+    ("interface_missing", "No interface given"),
+    ("interface_not_found", "Interface not found"),
+    ("interface_only_one", "You only have one connection!"),
+    ("interface_last", "Can't disable all networks"),
+    ("interface_enabled", "{interface} has been enabled"),
+    ("interface_disabled", "{interface} has been disabled"),
+    (
+        "interface_already_enabled",
+        "{interface} is already enabled",
+    ),
+    (
+        "interface_already_disabled",
+        "{interface} is already disabled",
+    ),
+    (
+        "interface_state_invalid",
+        "Use on or off after the interface",
+    ),
+    ("label_changed", "{interface} is now called {name}"),
+    ("label_removed", "{interface} has its own name again"),
+    (
+        "label_taken",
+        "{name} is already the name of another interface",
+    ),
+    ("label_not_saved", "Couldn't save the name: {error}"),
+    (
+        "interface_not_confirmed",
+        "The BELABOX didn't confirm the change of {interface}",
+    ),
+    (
+        "modem_restart_missing",
+        "No interface given, use one of: {interfaces}",
+    ),
+    (
+        "modem_restart_not_found",
+        "Interface not found, use one of: {interfaces}",
+    ),
+    ("modem_restart_disabled", "{interface} is disabled"),
+    (
+        "modem_restart_last",
+        "Can't restart the only enabled network",
+    ),
+    ("modem_restarting", "Restarting {interface}"),
+    ("modem_restarted", "{interface} has been restarted"),
+    ("sensors_unavailable", "Sensors not available"),
+    ("modems_unavailable", "Modems not available"),
+    (
+        "signal_modem",
+        "{interface}: {network_type} {signal}% {carrier}",
+    ),
+    ("connection", "Relay: {relay} ({address})"),
+    ("connection_not_connected", "Not connected to a relay"),
+    (
+        "raw_disabled",
+        "Raw messages are disabled, set allow_raw to enable them",
+    ),
+    ("raw_invalid", "The message has to be a JSON object"),
+    ("raw_response", "Response: {response}"),
+    ("raw_sent", "Sent, no response"),
+    ("sensor", "Temp: {temperature}"),
+    ("sensor_voltage", "{sensor}, Voltage: {voltage}"),
+    ("sensor_current", "{sensor}, Amps: {current}"),
+    ("sensor_other", "{sensor}, {label}: {value}"),
+    ("latency_current", "Current SRT latency is {latency} ms"),
+    ("latency_changed", "Changed SRT latency to {latency} ms"),
+    ("audio_delay_current", "Current audio delay is {delay} ms"),
+    ("audio_delay_changed", "Changed audio delay to {delay} ms"),
+    ("pipelines", "Pipelines: {pipelines}"),
+    ("pipelines_unavailable", "No pipelines found"),
+    ("pipeline_not_found", "Pipeline not found"),
+    ("pipeline_changed", "Changed pipeline to {pipeline}"),
+    ("audio_sources_unavailable", "No audio sources found"),
+    ("audio_source_not_found", "Audio source not found"),
+    ("audio_source_changed", "Changed audio to {source}"),
+    ("audio", "Audio: {source}"),
+    ("stream_started", "The stream started"),
+    ("stream_stopped", "The stream stopped"),
+    ("bitrate_low", "Low bitrate, {bitrate} kbps"),
+    ("bitrate_recovered", "Bitrate recovered, {bitrate} kbps"),
+    ("modem_connected", "{interfaces} is now connected"),
+    ("modems_connected", "{interfaces} are now connected"),
+    ("modem_disconnected", "{interfaces} has disconnected"),
+    ("modems_disconnected", "{interfaces} have disconnected"),
+    ("temperature_high", "High temperature, {temperature} °C"),
+    (
+        "cloud_disconnected",
+        "Lost the connection to BELABOX Cloud, reconnecting",
+    ),
+    ("cloud_reconnected", "Reconnected to BELABOX Cloud"),
+    ("encoder_offline", "The BELABOX went offline"),
+    ("encoder_online", "The BELABOX is back online"),
+    ("monitor_ups", "UPS {ups}"),
+];
+
+/// The chat messages of the bot, falls back to the built-in message when
+/// the config doesn't contain a key.
+#[derive(Debug, Default)]
+pub struct Templates {
+    templates: RwLock<BTreeMap<String, String>>,
+}
+
+impl Templates {
+    pub fn new(templates: BTreeMap<String, String>) -> Self {
+        Self {
+            templates: RwLock::new(templates),
+        }
+    }
+
+    /// Replaces the messages, for example after reloading the config.
+    pub fn replace(&self, templates: BTreeMap<String, String>) {
+        *self.templates.write().unwrap() = templates;
+    }
+
+    /// Renders a message without placeholders.
+    pub fn get(&self, key: &str) -> String {
+        self.render(key, &[])
+    }
+
+    /// Renders a message, `{name}` is replaced with the value of the argument
+    /// with the same name. Unknown placeholders are kept as they are.
+    pub fn render(&self, key: &str, args: &[(&str, &dyn Display)]) -> String {
+        let templates = self.templates.read().unwrap();
+        let template = match templates.get(key) {
+            Some(t) => t.as_str(),
+            None => DEFAULT_TEMPLATES
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, t)| *t)
+                .unwrap_or(key),
+        };
+
+        let mut message = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            message.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let end = match rest.find('}') {
+                Some(e) => e,
+                None => break,
+            };
+
+            let name = &rest[1..end];
+            match args.iter().find(|(n, _)| *n == name) {
+                Some((_, value)) => {
+                    let _ = write!(message, "{}", value);
+                }
+                None => message.push_str(&rest[..=end]),
+            }
+
+            rest = &rest[end + 1..];
+        }
+
+        message.push_str(rest);
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_placeholders() {
+        let templates = Templates::default();
+        let message = templates.render("bitrate_changed", &[("bitrate", &5000)]);
+
+        assert_eq!(message, "Changed max bitrate to 5000 kbps");
+    }
+
+    #[test]
+    fn render_custom() {
+        let mut custom = BTreeMap::new();
+        custom.insert(
+            "bitrate_changed".to_string(),
+            "Bitrate alterado para {bitrate} kbps {unknown}".to_string(),
+        );
+
+        let templates = Templates::new(custom);
+        let message = templates.render("bitrate_changed", &[("bitrate", &5000)]);
+
+        assert_eq!(message, "Bitrate alterado para 5000 kbps {unknown}");
+        assert_eq!(templates.get("offline"), "Offline :(");
+    }
+}
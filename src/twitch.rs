@@ -1,11 +1,19 @@
-use std::sync::{Arc, Weak};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Weak},
+};
 
+use rand::Rng;
 use thiserror::Error;
-use tokio::sync::broadcast;
-use tokio::task::JoinHandle;
-use tracing::{error, info};
+use tokio::{
+    sync::{broadcast, mpsc, RwLock},
+    task::JoinHandle,
+    time::{self, Duration, Instant},
+};
+use tracing::{debug, error, info, warn};
 use twitch_irc::{
-    login::StaticLoginCredentials,
+    login::LoginCredentials,
     message::{self, ServerMessage},
     transport::tcp::{TCPTransport, TLS},
     ClientConfig, SecureTCPTransport, TwitchIRCClient,
@@ -13,106 +21,658 @@ use twitch_irc::{
 
 use crate::{config, error::Error};
 
+mod eventsub;
+pub mod oauth;
+mod rate_limit;
+mod roles;
+mod whisper;
+
+pub use oauth::Credentials;
+use rate_limit::RateLimiter;
+use roles::Roles;
+
 #[derive(Error, Debug)]
 pub enum TwitchError {
     #[error("disconnected from twitch")]
     Disconnected,
     #[error("twitch error")]
-    TwitchIrc(#[from] twitch_irc::Error<TCPTransport<TLS>, StaticLoginCredentials>),
+    TwitchIrc(#[from] twitch_irc::Error<TCPTransport<TLS>, Credentials>),
+    #[error("the twitch oauth is invalid or expired")]
+    InvalidOauth,
+    #[error("the twitch oauth is missing the scopes: {}", .0.join(", "))]
+    MissingScopes(Vec<String>),
+    #[error("twitch oauth error")]
+    OAuth(#[from] oauth::OAuthError),
+    #[error("twitch api error")]
+    Api(#[from] reqwest::Error),
+    #[error("failed to whisper: {0}")]
+    Whisper(String),
+    #[error("eventsub websocket error")]
+    EventSubSocket(#[source] Box<tokio_tungstenite::tungstenite::Error>),
+    #[error("failed to subscribe to eventsub: {0}")]
+    EventSub(String),
+    #[error("invalid eventsub message")]
+    EventSubMessage(#[from] serde_json::Error),
 }
 
 #[derive(Debug, Clone)]
 pub struct HandleMessage {
     pub channel_name: String,
     pub sender_name: String,
+    /// Used to whisper the sender
+    pub sender_id: String,
     pub broadcaster: bool,
     pub moderator: bool,
     pub vip: bool,
+    pub subscriber: bool,
     pub message: String,
+    /// Set for a redeemed channel point reward, `message` only has the
+    /// arguments of the command
+    pub reward: Option<Redeemed>,
+}
+
+/// A redeemed channel point reward.
+#[derive(Debug, Clone)]
+pub struct Redeemed {
+    pub reward: config::Reward,
+    /// The text the viewer entered
+    pub input: String,
 }
 
+impl HandleMessage {
+    /// Whether the sender owns the channel, by the badge or the name because
+    /// messages without badges such as redemptions don't have the role.
+    pub fn is_broadcaster(&self) -> bool {
+        self.broadcaster || self.sender_name.eq_ignore_ascii_case(&self.channel_name)
+    }
+}
+
+type Client = TwitchIRCClient<TCPTransport<TLS>, Credentials>;
+type Incoming = mpsc::UnboundedReceiver<ServerMessage>;
+
+/// How often the connection to the channels is checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Twitch doesn't allow longer chat messages.
+const MAX_MESSAGE_LENGTH: usize = 500;
+
+/// The join message is posted at most this often in a channel, so a flaky
+/// connection doesn't repeat it on every reconnect.
+const JOIN_MESSAGE_COOLDOWN: Duration = Duration::from_secs(10 * 60);
+
 pub struct Twitch {
     pub read_handle: JoinHandle<()>,
-    pub client: TwitchIRCClient<TCPTransport<TLS>, StaticLoginCredentials>,
+    pub client: Arc<RwLock<Client>>,
     message_tx: Weak<broadcast::Sender<HandleMessage>>,
-    channel: String,
+    channels: Vec<String>,
+    rate_limit: Arc<RateLimiter>,
+    credentials: Credentials,
+    /// Moderators and VIPs from the Twitch API
+    roles: Arc<Roles>,
+    roles_handle: JoinHandle<()>,
+    /// Reads the channel point redemptions when they're configured
+    eventsub_handle: Option<JoinHandle<()>>,
+    /// Set for a client which never connects, messages are printed instead
+    /// of sent
+    offline: Option<Arc<broadcast::Sender<HandleMessage>>>,
+    /// Put around every part of a message
+    reply_prefix: String,
+    reply_suffix: String,
+}
+
+/// The channels to join and the message posted after joining one.
+struct Channels {
+    names: Vec<String>,
+    /// The parts of the join message, empty to join silently
+    join_message: Vec<String>,
+    /// When the join message was last posted in a channel
+    announced: HashMap<String, Instant>,
+}
+
+impl Channels {
+    /// The join message, unless it was posted in the channel recently.
+    fn join_message(&mut self, channel: &str) -> Option<Vec<String>> {
+        if self.join_message.is_empty() {
+            return None;
+        }
+
+        let now = Instant::now();
+
+        if let Some(last) = self.announced.get(channel) {
+            if now.duration_since(*last) < JOIN_MESSAGE_COOLDOWN {
+                debug!(channel, "join message posted recently, not again");
+                return None;
+            }
+        }
+
+        self.announced.insert(channel.to_owned(), now);
+
+        Some(self.join_message.to_owned())
+    }
+}
+
+/// Why reading the messages of a client stopped.
+enum ReadEnd {
+    /// The connection was lost, a new client should be created
+    Disconnected,
+    /// Login failed and there is no way to get a new oauth
+    LoginFailed,
 }
 
 impl Twitch {
-    pub async fn run(settings: config::Twitch) -> Result<Self, Error> {
+    /// Connects to Twitch chat, refreshed oauths are saved to the config at
+    /// `path` when given.
+    pub async fn run(settings: config::Twitch, path: Option<PathBuf>) -> Result<Self, Error> {
         let config::Twitch {
             bot_username,
             bot_oauth,
-            channel,
+            channels,
+            refresh,
+            channel_points,
+            reply_prefix,
+            reply_suffix,
+            join_message,
             ..
         } = settings;
 
         let username = bot_username.to_lowercase();
-        let channel = channel.to_lowercase();
+        let channels = channels
+            .iter()
+            .map(|c| c.to_lowercase())
+            .collect::<Vec<String>>();
         let mut oauth = bot_oauth;
 
         if let Some(strip_oauth) = oauth.strip_prefix("oauth:") {
             oauth = strip_oauth.to_string();
         }
 
-        let twitch_credentials = StaticLoginCredentials::new(username, Some(oauth));
-        let twitch_config = ClientConfig::new_simple(twitch_credentials);
-        let (mut incoming_messages, client) =
-            TwitchIRCClient::<SecureTCPTransport, StaticLoginCredentials>::new(twitch_config);
+        // A refreshable oauth gets replaced when it's invalid
+        if refresh.is_none() {
+            oauth::validate(&username, &oauth).await?;
+        }
+
+        let (twitch_credentials, token_storage) = Credentials::new(username, oauth, refresh, path);
+        let (incoming_messages, client) = new_client(twitch_credentials.clone());
+
+        for channel in &channels {
+            client.join(channel.to_owned())?;
+        }
 
         info!("Connected");
 
         let (tx, _) = broadcast::channel(100);
         let message_tx = Arc::new(tx);
+        let client = Arc::new(RwLock::new(client));
+        let rate_limit = Arc::new(RateLimiter::default());
 
-        let tx_read = message_tx.clone();
-        let read_handle = tokio::spawn(async move {
-            while let Some(message) = incoming_messages.recv().await {
-                match message {
-                    ServerMessage::Notice(msg) => {
-                        error!("{}", msg.message_text);
-                        if msg.message_text == "Login authentication failed" {
-                            break;
-                        }
-                    }
-                    ServerMessage::Privmsg(msg) => {
-                        let _ = tx_read.send(HandleMessage::from(msg));
-                    }
-                    _ => (),
-                }
-            }
+        let roles = Arc::new(Roles::default());
+        let roles_handle = {
+            let roles = roles.clone();
+            let credentials = twitch_credentials.clone();
+            let channels = channels.to_owned();
+
+            tokio::spawn(async move { roles.refresh_loop(credentials, channels).await })
+        };
+
+        let eventsub_handle = channel_points.map(|settings| {
+            tokio::spawn(eventsub::run(
+                settings,
+                channels.to_owned(),
+                message_tx.clone(),
+            ))
         });
 
-        client.join(channel.to_owned())?;
+        let reply_prefix = reply_prefix.trim().to_owned();
+        let reply_suffix = reply_suffix.trim().to_owned();
+
+        let join_message = join_message
+            .filter(|m| !m.trim().is_empty())
+            .map(|m| signed_parts(&m, &reply_prefix, &reply_suffix))
+            .unwrap_or_default();
+
+        let read_handle = tokio::spawn(reconnect_loop(
+            incoming_messages,
+            client.clone(),
+            twitch_credentials.clone(),
+            token_storage,
+            Channels {
+                names: channels.to_owned(),
+                join_message,
+                announced: HashMap::new(),
+            },
+            message_tx.clone(),
+            rate_limit.clone(),
+        ));
 
         Ok(Self {
             client,
             read_handle,
             message_tx: Arc::downgrade(&message_tx),
-            channel,
+            channels,
+            rate_limit,
+            credentials: twitch_credentials,
+            roles,
+            roles_handle,
+            eventsub_handle,
+            offline: None,
+            reply_prefix,
+            reply_suffix,
         })
     }
 
+    /// A client which never connects, the messages it would send are
+    /// printed and chat messages come from [`Twitch::simulate`].
+    #[cfg(any(test, feature = "mock"))]
+    pub fn offline(channels: Vec<String>) -> Self {
+        let (credentials, _) = Credentials::new("belabot".to_owned(), String::new(), None, None);
+        let (_, client) = new_client(credentials.clone());
+        let (tx, _) = broadcast::channel(100);
+        let message_tx = Arc::new(tx);
+
+        Self {
+            read_handle: tokio::spawn(async {}),
+            client: Arc::new(RwLock::new(client)),
+            message_tx: Arc::downgrade(&message_tx),
+            channels,
+            rate_limit: Arc::new(RateLimiter::default()),
+            credentials,
+            roles: Arc::new(Roles::default()),
+            roles_handle: tokio::spawn(async {}),
+            eventsub_handle: None,
+            offline: Some(message_tx),
+            reply_prefix: String::new(),
+            reply_suffix: String::new(),
+        }
+    }
+
+    /// Handles the message as if it was sent in chat, for an offline client.
+    #[cfg(any(test, feature = "mock"))]
+    pub fn simulate(&self, message: HandleMessage) -> Result<(), TwitchError> {
+        let tx = self.offline.as_ref().ok_or(TwitchError::Disconnected)?;
+        let _ = tx.send(message);
+
+        Ok(())
+    }
+
+    /// Whispers the user with the id instead of replying in chat.
+    pub async fn whisper(&self, user_id: &str, message: String) -> Result<(), TwitchError> {
+        if self.offline.is_some() {
+            for part in self.parts(&message) {
+                println!("[whisper to {}] {}", user_id, part);
+            }
+
+            return Ok(());
+        }
+
+        let token = match self.credentials.get_credentials().await?.token {
+            Some(t) => t,
+            None => return Err(TwitchError::InvalidOauth),
+        };
+
+        for part in self.parts(&message) {
+            whisper::send(&token, user_id, &part).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Leaves the channels, the messages which are being sent are written
+    /// first.
+    pub async fn close(&self) {
+        self.read_handle.abort();
+        self.roles_handle.abort();
+
+        if let Some(handle) = &self.eventsub_handle {
+            handle.abort();
+        }
+
+        if self.offline.is_some() {
+            return;
+        }
+
+        // Waits until sending the current messages is done
+        let client = self.client.write().await;
+
+        for channel in &self.channels {
+            client.part(channel.to_owned());
+        }
+
+        // Resolves once the ping is written, so after the queued PARTs
+        if let Err(e) = client.ping().await {
+            warn!(?e, "failed to leave the channels");
+        }
+
+        info!("Left the channels");
+    }
+
+    /// Whether the bot is in every channel, always `false` when offline.
+    pub async fn is_joined(&self) -> bool {
+        if self.offline.is_some() {
+            return false;
+        }
+
+        let client = self.client.read().await;
+
+        for channel in &self.channels {
+            let (_, joined) = client.get_channel_status(channel.to_owned()).await;
+
+            if !joined {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Adds the moderators and VIPs known from the Twitch API to the roles of
+    /// the message.
+    pub async fn apply_roles(&self, message: &mut HandleMessage) {
+        self.roles.apply(message).await;
+    }
+
     pub fn message_stream(&self) -> Result<broadcast::Receiver<HandleMessage>, TwitchError> {
         let tx = self.message_tx.upgrade().ok_or(TwitchError::Disconnected)?;
 
         Ok(tx.subscribe())
     }
 
+    /// Sends the message to every channel.
     pub async fn send(&self, message: String) -> Result<(), TwitchError> {
-        self.client
-            .say(self.channel.to_owned(), message)
-            .await
-            .map_err(TwitchError::TwitchIrc)
+        for channel in &self.channels {
+            self.send_to(channel, message.to_owned()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends the message to one channel, for example as a reply.
+    ///
+    /// Long messages are split up, and messages are delayed or dropped to
+    /// stay below the Twitch rate limits. When a part is dropped the parts
+    /// after it are as well, they make no sense on their own.
+    pub async fn send_to(&self, channel: &str, message: String) -> Result<(), TwitchError> {
+        for chunk in self.parts(&message) {
+            let wait = match self.rate_limit.acquire(channel).await {
+                Some(w) => w,
+                None => {
+                    warn!(
+                        channel,
+                        chunk, "rate limited, dropping the rest of the message"
+                    );
+                    break;
+                }
+            };
+
+            if !wait.is_zero() {
+                info!(channel, ?wait, "rate limited, delaying message");
+                time::sleep(wait).await;
+            }
+
+            if self.offline.is_some() {
+                println!("[#{}] {}", channel, chunk);
+                continue;
+            }
+
+            self.client
+                .read()
+                .await
+                .say(channel.to_owned(), chunk)
+                .await
+                .map_err(TwitchError::TwitchIrc)?;
+        }
+
+        Ok(())
+    }
+
+    /// The message split into parts which fit into a chat message together
+    /// with the prefix and suffix, which every part gets.
+    fn parts(&self, message: &str) -> Vec<String> {
+        signed_parts(message, &self.reply_prefix, &self.reply_suffix)
+    }
+}
+
+/// Splits the message so every part with the prefix and suffix around it is
+/// at most `MAX_MESSAGE_LENGTH` bytes.
+fn signed_parts(message: &str, prefix: &str, suffix: &str) -> Vec<String> {
+    let signature = [prefix, suffix]
+        .iter()
+        .filter(|s| !s.is_empty())
+        .map(|s| s.len() + 1)
+        .sum::<usize>();
+    let max = MAX_MESSAGE_LENGTH.saturating_sub(signature).max(1);
+
+    split_message(message, max)
+        .into_iter()
+        .map(|part| {
+            [prefix, part, suffix]
+                .iter()
+                .filter(|s| !s.is_empty())
+                .copied()
+                .collect::<Vec<&str>>()
+                .join(" ")
+        })
+        .collect()
+}
+
+/// Splits the message into parts of at most `max` bytes, preferably after a
+/// comma or a space.
+fn split_message(message: &str, max: usize) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = message.trim();
+
+    while rest.len() > max {
+        let mut end = max;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        let head = &rest[..end];
+        let split = head
+            .rfind(", ")
+            .map(|i| i + 1)
+            .or_else(|| head.rfind(' '))
+            .filter(|&i| i > 0)
+            .unwrap_or(end);
+
+        parts.push(rest[..split].trim_end());
+        rest = rest[split..].trim_start();
+    }
+
+    if !rest.is_empty() {
+        parts.push(rest);
+    }
+
+    parts
+}
+
+fn new_client(credentials: Credentials) -> (Incoming, Client) {
+    let twitch_config = ClientConfig::new_simple(credentials);
+    TwitchIRCClient::<SecureTCPTransport, Credentials>::new(twitch_config)
+}
+
+/// Reads the chat messages and replaces the client when the channels can't be
+/// joined anymore.
+async fn reconnect_loop(
+    mut incoming_messages: Incoming,
+    client: Arc<RwLock<Client>>,
+    credentials: Credentials,
+    token_storage: Option<oauth::ConfigTokenStorage>,
+    mut channels: Channels,
+    message_tx: Arc<broadcast::Sender<HandleMessage>>,
+    rate_limit: Arc<RateLimiter>,
+) {
+    let mut retry_grow = 0;
+
+    loop {
+        let end = read_messages(
+            &mut incoming_messages,
+            &client,
+            &token_storage,
+            &mut channels,
+            &message_tx,
+            &rate_limit,
+            &mut retry_grow,
+        )
+        .await;
+
+        if let ReadEnd::LoginFailed = end {
+            break;
+        }
+
+        // Exponential backoff with jitter so we don't reconnect in lockstep
+        let wait = Duration::from_secs(1 << retry_grow)
+            + Duration::from_millis(rand::thread_rng().gen_range(0..1000));
+        warn!("Disconnected from Twitch");
+        info!(
+            attempt = retry_grow + 1,
+            "trying to reconnect in {:?}", wait
+        );
+        time::sleep(wait).await;
+
+        if retry_grow < 6 {
+            retry_grow += 1;
+        }
+
+        let (new_incoming, new_client) = new_client(credentials.clone());
+        let joined = channels
+            .names
+            .iter()
+            .try_for_each(|channel| new_client.join(channel.to_owned()));
+
+        if let Err(e) = joined {
+            error!(?e, "failed to join channel");
+            break;
+        }
+
+        incoming_messages = new_incoming;
+        *client.write().await = new_client;
+    }
+}
+
+async fn read_messages(
+    incoming_messages: &mut Incoming,
+    client: &Arc<RwLock<Client>>,
+    token_storage: &Option<oauth::ConfigTokenStorage>,
+    channels: &mut Channels,
+    message_tx: &broadcast::Sender<HandleMessage>,
+    rate_limit: &Arc<RateLimiter>,
+    retry_grow: &mut u32,
+) -> ReadEnd {
+    let mut check = time::interval(CHECK_INTERVAL);
+    check.tick().await;
+
+    let mut joined = true;
+
+    loop {
+        tokio::select! {
+            message = incoming_messages.recv() => {
+                let message = match message {
+                    Some(m) => m,
+                    None => return ReadEnd::Disconnected,
+                };
+
+                match message {
+                    ServerMessage::Notice(msg) => {
+                        error!("{}", msg.message_text);
+                        if msg.message_text == "Login authentication failed" {
+                            // Refresh the oauth on the next connection attempt
+                            match token_storage {
+                                Some(storage) => storage.expire(),
+                                None => return ReadEnd::LoginFailed,
+                            }
+                        }
+                    }
+                    ServerMessage::Join(msg) if channels.names.contains(&msg.channel_login) => {
+                        info!("Joined {}", msg.channel_login);
+                        *retry_grow = 0;
+
+                        // Posted in the background, waiting for the rate limit
+                        // would stop reading chat
+                        if let Some(parts) = channels.join_message(&msg.channel_login) {
+                            tokio::spawn(join_message(
+                                client.clone(),
+                                rate_limit.clone(),
+                                msg.channel_login,
+                                parts,
+                            ));
+                        }
+                    }
+                    ServerMessage::UserState(msg) => {
+                        let elevated = msg
+                            .badges
+                            .iter()
+                            .any(|b| b.name == "moderator" || b.name == "broadcaster");
+
+                        rate_limit.set_elevated(&msg.channel_login, elevated).await;
+                    }
+                    // Only the configured channels, in case the bot ends up
+                    // in another one
+                    ServerMessage::Privmsg(msg) if channels.names.contains(&msg.channel_login) => {
+                        let _ = message_tx.send(HandleMessage::from(msg));
+                    }
+                    ServerMessage::Privmsg(msg) => {
+                        debug!(
+                            channel = msg.channel_login,
+                            "ignoring message from a channel which isn't configured"
+                        );
+                    }
+                    _ => (),
+                }
+            }
+            _ = check.tick() => {
+                let mut is_joined = true;
+
+                for channel in &channels.names {
+                    let (_, channel_joined) = client
+                        .read()
+                        .await
+                        .get_channel_status(channel.to_owned())
+                        .await;
+
+                    is_joined &= channel_joined;
+                }
+
+                // Give the client one interval to reconnect by itself
+                if !is_joined && !joined {
+                    return ReadEnd::Disconnected;
+                }
+
+                joined = is_joined;
+            }
+        }
+    }
+}
+
+/// Posts the join message in the channel, within the rate limits.
+async fn join_message(
+    client: Arc<RwLock<Client>>,
+    rate_limit: Arc<RateLimiter>,
+    channel: String,
+    parts: Vec<String>,
+) {
+    for part in parts {
+        match rate_limit.acquire(&channel).await {
+            Some(wait) => time::sleep(wait).await,
+            None => {
+                warn!(channel, "rate limited, not posting the join message");
+                return;
+            }
+        }
+
+        let said = client.read().await.say(channel.to_owned(), part).await;
+
+        if let Err(e) = said {
+            warn!(?e, channel, "failed to post the join message");
+            return;
+        }
     }
 }
 
 impl From<message::PrivmsgMessage> for HandleMessage {
     fn from(m: message::PrivmsgMessage) -> Self {
-        let broadcaster = m.badges.contains(&message::Badge {
-            name: "broadcaster".to_string(),
-            version: "1".to_string(),
-        });
+        // The channel id also matches when the badge is hidden
+        let broadcaster =
+            m.sender.id == m.channel_id || m.badges.iter().any(|b| b.name == "broadcaster");
 
         let moderator = m.badges.contains(&message::Badge {
             name: "moderator".to_string(),
@@ -124,13 +684,92 @@ impl From<message::PrivmsgMessage> for HandleMessage {
             version: "1".to_string(),
         });
 
+        // The version of the subscriber badge is the amount of months
+        let subscriber = m
+            .badges
+            .iter()
+            .any(|b| b.name == "subscriber" || b.name == "founder");
+
         Self {
             channel_name: m.channel_login,
             sender_name: m.sender.login,
+            sender_id: m.sender.id,
             broadcaster,
             moderator,
             vip,
+            subscriber,
             message: m.message_text,
+            reward: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_long_message() {
+        assert_eq!(split_message("short", 10), vec!["short"]);
+        assert_eq!(
+            split_message("eth0: 100, usb0: 200, usb1: 300", 12),
+            vec!["eth0: 100,", "usb0: 200,", "usb1: 300"]
+        );
+        assert_eq!(
+            split_message("aaaaaaaaaaaa", 5),
+            vec!["aaaaa", "aaaaa", "aa"]
+        );
+        assert_eq!(split_message("ééé", 3), vec!["é", "é", "é"]);
+    }
+
+    #[test]
+    fn broadcaster() {
+        let mut message = HandleMessage {
+            channel_name: "715209".to_string(),
+            sender_name: "715209".to_string(),
+            sender_id: "1".to_string(),
+            broadcaster: false,
+            moderator: false,
+            vip: false,
+            subscriber: false,
+            message: "!bbs".to_string(),
+            reward: None,
+        };
+        assert!(message.is_broadcaster());
+
+        message.sender_name = "viewer".to_string();
+        assert!(!message.is_broadcaster());
+
+        message.broadcaster = true;
+        assert!(message.is_broadcaster());
+    }
+
+    #[test]
+    fn join_message_once() {
+        let mut channels = Channels {
+            names: vec!["715209".to_string(), "other".to_string()],
+            join_message: vec!["belabot is online".to_string()],
+            announced: HashMap::new(),
+        };
+
+        assert!(channels.join_message("715209").is_some());
+        assert!(channels.join_message("715209").is_none());
+        assert!(channels.join_message("other").is_some());
+
+        channels.join_message.clear();
+        channels.announced.clear();
+        assert!(channels.join_message("715209").is_none());
+    }
+
+    #[test]
+    fn signed_long_message() {
+        assert_eq!(signed_parts("hi", "", ""), vec!["hi"]);
+        assert_eq!(signed_parts("hi", "[belabot]", ""), vec!["[belabot] hi"]);
+
+        let parts = signed_parts(&"word ".repeat(200), "[belabot]", "!");
+        assert_eq!(parts.len(), 3);
+        assert!(parts.iter().all(|p| p.len() <= MAX_MESSAGE_LENGTH));
+        assert!(parts.iter().all(|p| p.starts_with("[belabot] word")));
+        assert!(parts.iter().all(|p| p.ends_with("word !")));
+    }
+}
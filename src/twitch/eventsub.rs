@@ -0,0 +1,360 @@
+use std::{collections::HashMap, sync::Arc};
+
+use futures_util::StreamExt;
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::{
+    sync::broadcast,
+    time::{self, Duration},
+};
+use tokio_tungstenite::tungstenite::Message as TMessage;
+use tracing::{debug, error, info, warn};
+
+use super::{oauth, HandleMessage, Redeemed, TwitchError};
+use crate::config::{ChannelPoints, Reward};
+
+const EVENTSUB_WS: &str = "wss://eventsub.wss.twitch.tv/ws";
+const SUBSCRIPTIONS_URL: &str = "https://api.twitch.tv/helix/eventsub/subscriptions";
+const REDEMPTION_TYPE: &str = "channel.channel_points_custom_reward_redemption.add";
+
+/// Either scope allows reading the redemptions.
+const REDEMPTION_SCOPES: [&str; 2] = ["channel:read:redemptions", "channel:manage:redemptions"];
+
+/// The keepalive timeout until the welcome message gives the real one.
+const KEEPALIVE_SECS: u64 = 30;
+
+/// Extra time after the keepalive timeout before the session counts as dead.
+const KEEPALIVE_GRACE: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize)]
+struct Frame {
+    metadata: Metadata,
+    #[serde(default)]
+    payload: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct Metadata {
+    message_type: String,
+}
+
+#[derive(Deserialize)]
+struct SessionPayload {
+    session: Session,
+}
+
+#[derive(Deserialize)]
+struct Session {
+    id: String,
+    keepalive_timeout_seconds: Option<u64>,
+    reconnect_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct NotificationPayload {
+    event: Redemption,
+}
+
+#[derive(Deserialize)]
+struct Redemption {
+    broadcaster_user_login: String,
+    user_id: String,
+    user_login: String,
+    #[serde(default)]
+    user_input: String,
+    reward: RedeemedReward,
+}
+
+#[derive(Deserialize)]
+struct RedeemedReward {
+    id: String,
+    title: String,
+}
+
+/// Why a session ended.
+enum SessionEnd {
+    /// Twitch moved the session, the subscription moves with it
+    Reconnect(String),
+    Closed,
+}
+
+/// Reads the channel point redemptions of the broadcaster and sends the ones
+/// with a configured reward as commands.
+pub async fn run(
+    settings: ChannelPoints,
+    channels: Vec<String>,
+    message_tx: Arc<broadcast::Sender<HandleMessage>>,
+) {
+    let oauth = settings
+        .oauth
+        .strip_prefix("oauth:")
+        .unwrap_or(&settings.oauth)
+        .to_owned();
+    let mut url = EVENTSUB_WS.to_owned();
+    let mut subscribe = true;
+    let mut retry_grow = 0;
+
+    loop {
+        let end = session(
+            &oauth,
+            &url,
+            subscribe,
+            &settings.rewards,
+            &channels,
+            &message_tx,
+        )
+        .await;
+
+        match end {
+            Ok(SessionEnd::Reconnect(reconnect_url)) => {
+                debug!("moving the eventsub session");
+                url = reconnect_url;
+                subscribe = false;
+                retry_grow = 0;
+                continue;
+            }
+            Ok(SessionEnd::Closed) => warn!("The channel point redemptions disconnected"),
+            Err(e @ (TwitchError::InvalidOauth | TwitchError::MissingScopes(_))) => {
+                error!(
+                    "{}, check channel_points.oauth in the config, redemptions are ignored",
+                    e
+                );
+                break;
+            }
+            Err(e) => warn!(?e, "The channel point redemptions disconnected"),
+        }
+
+        url = EVENTSUB_WS.to_owned();
+        subscribe = true;
+
+        let wait = Duration::from_secs(1 << retry_grow)
+            + Duration::from_millis(rand::thread_rng().gen_range(0..1000));
+        info!("trying to read the redemptions again in {:?}", wait);
+        time::sleep(wait).await;
+
+        if retry_grow < 6 {
+            retry_grow += 1;
+        }
+    }
+}
+
+async fn session(
+    oauth: &str,
+    url: &str,
+    subscribe: bool,
+    rewards: &HashMap<String, Reward>,
+    channels: &[String],
+    message_tx: &broadcast::Sender<HandleMessage>,
+) -> Result<SessionEnd, TwitchError> {
+    let info = match oauth::token_info(oauth).await {
+        Ok(i) => i,
+        Err(e) if e.status() == Some(reqwest::StatusCode::UNAUTHORIZED) => {
+            return Err(TwitchError::InvalidOauth)
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    if !info
+        .scopes
+        .iter()
+        .any(|s| REDEMPTION_SCOPES.contains(&s.as_str()))
+    {
+        return Err(TwitchError::MissingScopes(vec![
+            REDEMPTION_SCOPES[0].to_owned()
+        ]));
+    }
+
+    let (stream, _) = tokio_tungstenite::connect_async(url)
+        .await
+        .map_err(|e| TwitchError::EventSubSocket(Box::new(e)))?;
+    let (_, mut read) = stream.split();
+
+    let mut keepalive = Duration::from_secs(KEEPALIVE_SECS) + KEEPALIVE_GRACE;
+
+    loop {
+        let message = match time::timeout(keepalive, read.next()).await {
+            Ok(Some(Ok(m))) => m,
+            Ok(Some(Err(e))) => return Err(TwitchError::EventSubSocket(Box::new(e))),
+            Ok(None) => return Ok(SessionEnd::Closed),
+            Err(_) => {
+                warn!("No keepalive from Twitch EventSub");
+                return Ok(SessionEnd::Closed);
+            }
+        };
+
+        let text = match message {
+            TMessage::Text(t) => t,
+            TMessage::Close(_) => return Ok(SessionEnd::Closed),
+            _ => continue,
+        };
+
+        let frame = match serde_json::from_str::<Frame>(&text) {
+            Ok(f) => f,
+            Err(e) => {
+                error!(?e, text, "failed to deserialize the eventsub message");
+                continue;
+            }
+        };
+
+        match frame.metadata.message_type.as_str() {
+            "session_welcome" => {
+                let payload: SessionPayload = serde_json::from_value(frame.payload)?;
+
+                if let Some(secs) = payload.session.keepalive_timeout_seconds {
+                    keepalive = Duration::from_secs(secs) + KEEPALIVE_GRACE;
+                }
+
+                if subscribe {
+                    subscribe_redemptions(oauth, &info, &payload.session.id).await?;
+                }
+
+                info!(
+                    broadcaster = info.login,
+                    "Listening for channel point redemptions"
+                );
+            }
+            "session_keepalive" => {}
+            "session_reconnect" => {
+                let payload: SessionPayload = serde_json::from_value(frame.payload)?;
+
+                if let Some(url) = payload.session.reconnect_url {
+                    return Ok(SessionEnd::Reconnect(url));
+                }
+            }
+            "notification" => {
+                let payload: NotificationPayload = serde_json::from_value(frame.payload)?;
+
+                if let Some(message) = redeemed(payload.event, rewards, channels) {
+                    let _ = message_tx.send(message);
+                }
+            }
+            "revocation" => {
+                warn!("Twitch revoked the channel point subscription");
+                return Ok(SessionEnd::Closed);
+            }
+            other => debug!(message_type = other, "unknown eventsub message"),
+        }
+    }
+}
+
+/// The redemptions of the broadcaster the oauth belongs to.
+async fn subscribe_redemptions(
+    oauth: &str,
+    info: &oauth::Validation,
+    session_id: &str,
+) -> Result<(), TwitchError> {
+    let response = reqwest::Client::new()
+        .post(SUBSCRIPTIONS_URL)
+        .bearer_auth(oauth)
+        .header("Client-Id", &info.client_id)
+        .json(&json!({
+            "type": REDEMPTION_TYPE,
+            "version": "1",
+            "condition": { "broadcaster_user_id": info.user_id },
+            "transport": { "method": "websocket", "session_id": session_id },
+        }))
+        .send()
+        .await?;
+
+    let status = response.status();
+
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(TwitchError::EventSub(format!("{} {}", status, body)));
+    }
+
+    Ok(())
+}
+
+/// The command of the redeemed reward, `None` when the reward isn't
+/// configured.
+fn redeemed(
+    redemption: Redemption,
+    rewards: &HashMap<String, Reward>,
+    channels: &[String],
+) -> Option<HandleMessage> {
+    let reward = match rewards.get(&redemption.reward.id) {
+        Some(r) => r,
+        None => {
+            debug!(
+                id = redemption.reward.id,
+                title = redemption.reward.title,
+                "redeemed reward isn't configured"
+            );
+            return None;
+        }
+    };
+
+    // Replies go to the channel, so the bot has to be in it
+    if !channels.contains(&redemption.broadcaster_user_login) {
+        warn!(
+            channel = redemption.broadcaster_user_login,
+            "Ignoring a redemption in a channel which isn't configured"
+        );
+        return None;
+    }
+
+    let input = redemption.user_input.trim().to_owned();
+
+    info!(
+        reward = redemption.reward.title,
+        user = redemption.user_login,
+        command = ?reward.command,
+        "Reward redeemed"
+    );
+
+    Some(HandleMessage {
+        channel_name: redemption.broadcaster_user_login,
+        sender_name: redemption.user_login,
+        sender_id: redemption.user_id,
+        broadcaster: false,
+        moderator: false,
+        vip: false,
+        subscriber: false,
+        message: reward.arguments.replace("{input}", &input),
+        reward: Some(Redeemed {
+            reward: reward.to_owned(),
+            input,
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BotCommand, Permission};
+
+    #[test]
+    fn redemption_command() {
+        let payload: NotificationPayload = serde_json::from_value(json!({
+            "subscription": { "type": REDEMPTION_TYPE },
+            "event": {
+                "broadcaster_user_login": "715209",
+                "user_id": "1",
+                "user_login": "viewer",
+                "user_input": " 6000 ",
+                "reward": { "id": "abc", "title": "Bitrate bump" }
+            }
+        }))
+        .unwrap();
+
+        let rewards = HashMap::from([(
+            "abc".to_string(),
+            Reward {
+                command: BotCommand::Bitrate,
+                arguments: "{input}".to_string(),
+                permission: Permission::Public,
+                min: Some(500),
+                max: Some(6000),
+            },
+        )]);
+
+        let message = redeemed(payload.event, &rewards, &["715209".to_string()]).unwrap();
+        assert_eq!(message.message, "6000");
+        let redeemed = message.reward.unwrap();
+        assert_eq!(redeemed.reward.command, BotCommand::Bitrate);
+        assert_eq!(redeemed.input, "6000");
+        assert!(!message.moderator);
+    }
+}
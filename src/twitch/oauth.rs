@@ -0,0 +1,272 @@
+use std::{
+    convert::Infallible,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::UNIX_EPOCH,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use thiserror::Error;
+use tracing::{error, info, warn};
+use twitch_irc::login::{
+    CredentialsPair, LoginCredentials, RefreshingLoginCredentials, RefreshingLoginError,
+    StaticLoginCredentials, TokenStorage, UserAccessToken,
+};
+
+use super::TwitchError;
+use crate::config::{self, ConfigError, Settings};
+
+const VALIDATE_URL: &str = "https://id.twitch.tv/oauth2/validate";
+
+/// Scopes needed to read and send chat messages.
+const CHAT_SCOPES: [&str; 2] = ["chat:read", "chat:edit"];
+
+/// Older oauths have this scope instead of the chat scopes.
+const LEGACY_CHAT_SCOPE: &str = "chat_login";
+
+#[derive(Debug, Deserialize)]
+pub struct Validation {
+    pub login: String,
+    #[serde(default)]
+    pub user_id: String,
+    #[serde(default)]
+    pub client_id: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum OAuthError {
+    #[error("{0}")]
+    Refresh(#[from] RefreshingLoginError<ConfigTokenStorage>),
+}
+
+/// Checks that the oauth is valid and can be used for chat.
+///
+/// Only an invalid oauth is an error, when Twitch can't be reached the login
+/// is tried anyway.
+pub async fn validate(username: &str, oauth: &str) -> Result<(), TwitchError> {
+    let response = reqwest::Client::new()
+        .get(VALIDATE_URL)
+        .header("Authorization", format!("OAuth {}", oauth))
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(r) => r,
+        Err(e) => {
+            warn!(?e, "unable to validate the twitch oauth");
+            return Ok(());
+        }
+    };
+
+    if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+        error!("The Twitch oauth is invalid or expired, generate a new one");
+        return Err(TwitchError::InvalidOauth);
+    }
+
+    let validation = match response.error_for_status() {
+        Ok(r) => r.json::<Validation>().await,
+        Err(e) => Err(e),
+    };
+
+    let validation = match validation {
+        Ok(v) => v,
+        Err(e) => {
+            warn!(?e, "unable to validate the twitch oauth");
+            return Ok(());
+        }
+    };
+
+    let missing = missing_scopes(&validation.scopes);
+    if !missing.is_empty() {
+        error!(?missing, "The Twitch oauth is missing the chat scopes");
+        return Err(TwitchError::MissingScopes(missing));
+    }
+
+    if !validation.login.eq_ignore_ascii_case(username) {
+        warn!(
+            login = validation.login,
+            bot_username = username,
+            "The Twitch oauth belongs to a different account"
+        );
+    }
+
+    info!(login = validation.login, "Validated the Twitch oauth");
+
+    Ok(())
+}
+
+/// Gets the account, client and scopes of the oauth.
+pub async fn token_info(oauth: &str) -> Result<Validation, reqwest::Error> {
+    reqwest::Client::new()
+        .get(VALIDATE_URL)
+        .header("Authorization", format!("OAuth {}", oauth))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+fn missing_scopes(scopes: &[String]) -> Vec<String> {
+    if scopes.iter().any(|s| s == LEGACY_CHAT_SCOPE) {
+        return Vec::new();
+    }
+
+    CHAT_SCOPES
+        .iter()
+        .filter(|&&c| !scopes.iter().any(|s| s == c))
+        .map(|c| c.to_string())
+        .collect()
+}
+
+/// The login credentials of the bot, refreshes the oauth when the config
+/// contains the refresh details.
+#[derive(Debug, Clone)]
+pub enum Credentials {
+    Static(StaticLoginCredentials),
+    Refreshing(RefreshingLoginCredentials<ConfigTokenStorage>),
+}
+
+impl Credentials {
+    /// Creates the credentials, the storage is returned when the oauth can be
+    /// refreshed.
+    pub fn new(
+        username: String,
+        oauth: String,
+        refresh: Option<config::TwitchRefresh>,
+        path: Option<PathBuf>,
+    ) -> (Self, Option<ConfigTokenStorage>) {
+        let refresh = match refresh {
+            Some(r) => r,
+            None => {
+                let credentials = StaticLoginCredentials::new(username, Some(oauth));
+                return (Self::Static(credentials), None);
+            }
+        };
+
+        // The age of the current token is unknown, the epoch makes sure it
+        // gets refreshed before connecting.
+        let token = UserAccessToken {
+            access_token: oauth,
+            refresh_token: refresh.refresh_token,
+            created_at: DateTime::<Utc>::from(UNIX_EPOCH),
+            expires_at: None,
+        };
+
+        let storage = ConfigTokenStorage {
+            path,
+            token,
+            expired: Arc::new(AtomicBool::new(false)),
+        };
+
+        let credentials = RefreshingLoginCredentials::init_with_username(
+            Some(username),
+            refresh.client_id,
+            refresh.client_secret,
+            storage.clone(),
+        );
+
+        (Self::Refreshing(credentials), Some(storage))
+    }
+}
+
+#[async_trait]
+impl LoginCredentials for Credentials {
+    type Error = OAuthError;
+
+    async fn get_credentials(&self) -> Result<CredentialsPair, OAuthError> {
+        match self {
+            Self::Static(c) => match c.get_credentials().await {
+                Ok(pair) => Ok(pair),
+                Err(e) => match e {},
+            },
+            Self::Refreshing(c) => Ok(c.get_credentials().await?),
+        }
+    }
+}
+
+/// Keeps the current token and writes refreshed tokens to the config file.
+#[derive(Debug, Clone)]
+pub struct ConfigTokenStorage {
+    path: Option<PathBuf>,
+    token: UserAccessToken,
+    expired: Arc<AtomicBool>,
+}
+
+impl ConfigTokenStorage {
+    /// Forces a refresh the next time the credentials are requested.
+    pub fn expire(&self) {
+        self.expired.store(true, Ordering::SeqCst);
+    }
+}
+
+#[async_trait]
+impl TokenStorage for ConfigTokenStorage {
+    type LoadError = Infallible;
+    type UpdateError = ConfigError;
+
+    async fn load_token(&mut self) -> Result<UserAccessToken, Infallible> {
+        let mut token = self.token.clone();
+
+        if self.expired.load(Ordering::SeqCst) {
+            token.created_at = DateTime::<Utc>::from(UNIX_EPOCH);
+        }
+
+        Ok(token)
+    }
+
+    async fn update_token(&mut self, token: &UserAccessToken) -> Result<(), ConfigError> {
+        info!("Refreshed the Twitch oauth");
+
+        self.token = token.clone();
+        self.expired.store(false, Ordering::SeqCst);
+
+        let path = match &self.path {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+
+        let result = Settings::update(path, |settings| {
+            // Keep the oauth in its own file when it's stored in one
+            if let Err(e) = config::set_secret(&mut settings.twitch.bot_oauth, &token.access_token)
+            {
+                warn!(?e, "failed to save the refreshed oauth to the secret file");
+            }
+
+            if let Some(refresh) = &mut settings.twitch.refresh {
+                refresh.refresh_token = token.refresh_token.to_owned();
+            }
+        });
+
+        // The new token is still usable, so don't fail the login
+        if let Err(e) = result {
+            warn!(?e, "failed to save the refreshed oauth");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chat_scopes() {
+        let scopes = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+
+        assert!(missing_scopes(&scopes(&["chat:read", "chat:edit"])).is_empty());
+        assert!(missing_scopes(&scopes(&["chat_login"])).is_empty());
+        assert_eq!(
+            missing_scopes(&scopes(&["chat:read"])),
+            vec!["chat:edit".to_string()]
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+
+use tokio::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Twitch allows this many messages per window in channels where the bot is
+/// not a moderator or the broadcaster.
+const LIMIT: f64 = 20.0;
+
+/// The limit in channels where the bot is a moderator or the broadcaster.
+const ELEVATED_LIMIT: f64 = 100.0;
+
+const WINDOW: Duration = Duration::from_secs(30);
+
+/// Messages which would have to wait longer than this are dropped.
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Token bucket per channel.
+///
+/// Twitch counts the messages in a sliding window, so only half of the limit
+/// can be sent as a burst and the other half refills over the window. This
+/// way no window can contain more than the limit.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Negative when messages are waiting
+    tokens: f64,
+    elevated: bool,
+    last: Instant,
+}
+
+impl Bucket {
+    fn new(elevated: bool) -> Self {
+        let mut bucket = Self {
+            tokens: 0.0,
+            elevated,
+            last: Instant::now(),
+        };
+
+        bucket.tokens = bucket.capacity();
+        bucket
+    }
+
+    fn capacity(&self) -> f64 {
+        let limit = if self.elevated { ELEVATED_LIMIT } else { LIMIT };
+
+        limit / 2.0
+    }
+
+    /// Tokens per second.
+    fn rate(&self) -> f64 {
+        self.capacity() / WINDOW.as_secs_f64()
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * self.rate()).min(self.capacity());
+        self.last = now;
+    }
+}
+
+impl RateLimiter {
+    /// Reserves a message in the channel and returns how long to wait before
+    /// sending it, `None` when the message should be dropped.
+    pub async fn acquire(&self, channel: &str) -> Option<Duration> {
+        let mut buckets = self.buckets.lock().await;
+        let bucket = buckets
+            .entry(channel.to_owned())
+            .or_insert_with(|| Bucket::new(false));
+
+        bucket.refill(Instant::now());
+
+        let wait = if bucket.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - bucket.tokens) / bucket.rate())
+        };
+
+        if wait > MAX_DELAY {
+            return None;
+        }
+
+        bucket.tokens -= 1.0;
+
+        Some(wait)
+    }
+
+    /// Sets whether the bot is a moderator or the broadcaster in the channel.
+    pub async fn set_elevated(&self, channel: &str, elevated: bool) {
+        let mut buckets = self.buckets.lock().await;
+        let bucket = buckets
+            .entry(channel.to_owned())
+            .or_insert_with(|| Bucket::new(elevated));
+
+        if bucket.elevated != elevated {
+            bucket.refill(Instant::now());
+            bucket.elevated = elevated;
+            bucket.tokens = bucket.tokens.min(bucket.capacity());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn burst_then_wait() {
+        let limiter = RateLimiter::default();
+
+        for _ in 0..10 {
+            assert_eq!(limiter.acquire("715209").await, Some(Duration::ZERO));
+        }
+
+        let wait = limiter.acquire("715209").await.unwrap();
+        assert!(wait > Duration::from_millis(2900) && wait <= Duration::from_secs(3));
+
+        // Other channels have their own bucket
+        assert_eq!(limiter.acquire("b3ck").await, Some(Duration::ZERO));
+
+        limiter.set_elevated("moderated", true).await;
+        for _ in 0..50 {
+            assert_eq!(limiter.acquire("moderated").await, Some(Duration::ZERO));
+        }
+    }
+
+    #[tokio::test]
+    async fn drop_when_full() {
+        let limiter = RateLimiter::default();
+
+        // 10 burst and 10 waiting for up to 30 seconds
+        for _ in 0..20 {
+            assert!(limiter.acquire("715209").await.is_some());
+        }
+
+        assert_eq!(limiter.acquire("715209").await, None);
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Deserialize;
+use tokio::{
+    sync::RwLock,
+    time::{self, Duration},
+};
+use tracing::{debug, info, warn};
+use twitch_irc::login::LoginCredentials;
+
+use super::{oauth, Credentials, HandleMessage, TwitchError};
+
+const USERS_URL: &str = "https://api.twitch.tv/helix/users";
+const MODERATORS_URL: &str = "https://api.twitch.tv/helix/moderation/moderators";
+const VIPS_URL: &str = "https://api.twitch.tv/helix/channels/vips";
+
+/// How often the moderators and VIPs are fetched again.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(300);
+
+/// The most users Helix returns per page.
+const PAGE_SIZE: &str = "100";
+
+#[derive(Debug, Default)]
+struct ChannelRoles {
+    moderators: HashSet<String>,
+    vips: HashSet<String>,
+}
+
+/// The moderators and VIPs of the channels according to the Twitch API, so
+/// roles are known even when the chat badges are missing.
+#[derive(Debug, Default)]
+pub struct Roles {
+    channels: RwLock<HashMap<String, ChannelRoles>>,
+}
+
+impl Roles {
+    /// Adds the roles from the API to the ones from the chat badges.
+    pub async fn apply(&self, message: &mut HandleMessage) {
+        let channels = self.channels.read().await;
+        let roles = match channels.get(&message.channel_name) {
+            Some(r) => r,
+            None => return,
+        };
+
+        message.moderator |= roles.moderators.contains(&message.sender_name);
+        message.vip |= roles.vips.contains(&message.sender_name);
+    }
+
+    /// Fetches the roles of every channel, the chat badges are used when the
+    /// API can't be used.
+    ///
+    /// The oauth needs the `moderator:read:moderators` and
+    /// `moderator:read:vips` scopes, or the broadcaster scopes
+    /// `moderation:read` and `channel:read:vips`.
+    pub async fn refresh_loop(&self, credentials: Credentials, channels: Vec<String>) {
+        let mut interval = time::interval(REFRESH_INTERVAL);
+        let mut failing = false;
+
+        loop {
+            interval.tick().await;
+
+            match self.refresh(&credentials, &channels).await {
+                Ok(()) => {
+                    if failing {
+                        info!("Fetching the moderators and VIPs works again");
+                    }
+
+                    failing = false;
+                }
+                Err(e) => {
+                    // Old roles would keep removed moderators, the badges are
+                    // up to date
+                    self.channels.write().await.clear();
+
+                    // Only warn once, a missing scope fails every time
+                    if !failing {
+                        warn!(
+                            ?e,
+                            "Unable to fetch the moderators and VIPs from Twitch, only using the chat badges"
+                        );
+                    }
+
+                    failing = true;
+                }
+            }
+        }
+    }
+
+    async fn refresh(
+        &self,
+        credentials: &Credentials,
+        channels: &[String],
+    ) -> Result<(), TwitchError> {
+        let token = credentials
+            .get_credentials()
+            .await?
+            .token
+            .ok_or(TwitchError::InvalidOauth)?;
+        let info = oauth::token_info(&token).await?;
+        let api = Api {
+            client: reqwest::Client::new(),
+            token,
+            client_id: info.client_id,
+        };
+
+        let ids = api.user_ids(channels).await?;
+        let mut fetched = HashMap::new();
+
+        for (channel, id) in ids {
+            let roles = ChannelRoles {
+                moderators: api.logins(MODERATORS_URL, &id).await?,
+                vips: api.logins(VIPS_URL, &id).await?,
+            };
+
+            debug!(
+                channel,
+                moderators = roles.moderators.len(),
+                vips = roles.vips.len(),
+                "fetched the channel roles"
+            );
+
+            fetched.insert(channel, roles);
+        }
+
+        // Replaced at once, so channels which weren't found don't keep their
+        // old roles
+        *self.channels.write().await = fetched;
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct Page<T> {
+    data: Vec<T>,
+    #[serde(default)]
+    pagination: Pagination,
+}
+
+#[derive(Deserialize, Default)]
+struct Pagination {
+    cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct User {
+    id: String,
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct RoleUser {
+    user_login: String,
+}
+
+struct Api {
+    client: reqwest::Client,
+    token: String,
+    client_id: String,
+}
+
+impl Api {
+    /// The user ids of the channels by login.
+    async fn user_ids(&self, channels: &[String]) -> Result<Vec<(String, String)>, TwitchError> {
+        let query = channels
+            .iter()
+            .map(|c| ("login", c.as_str()))
+            .collect::<Vec<_>>();
+
+        let page = self.get::<User>(USERS_URL, &query).await?;
+
+        Ok(page.data.into_iter().map(|u| (u.login, u.id)).collect())
+    }
+
+    /// Every login of a paginated role list.
+    async fn logins(
+        &self,
+        url: &str,
+        broadcaster_id: &str,
+    ) -> Result<HashSet<String>, TwitchError> {
+        let mut logins = HashSet::new();
+        let mut cursor: Option<String> = None;
+
+        loop {
+            let mut query = vec![("broadcaster_id", broadcaster_id), ("first", PAGE_SIZE)];
+            if let Some(cursor) = &cursor {
+                query.push(("after", cursor.as_str()));
+            }
+
+            let page = self.get::<RoleUser>(url, &query).await?;
+            logins.extend(page.data.into_iter().map(|u| u.user_login));
+
+            match page.pagination.cursor {
+                Some(c) if !c.is_empty() => cursor = Some(c),
+                _ => break,
+            }
+        }
+
+        Ok(logins)
+    }
+
+    async fn get<T>(&self, url: &str, query: &[(&str, &str)]) -> Result<Page<T>, TwitchError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let page = self
+            .client
+            .get(url)
+            .query(query)
+            .bearer_auth(&self.token)
+            .header("Client-Id", &self.client_id)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(page)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn apply_roles() {
+        let roles = Roles::default();
+        roles.channels.write().await.insert(
+            "715209".to_string(),
+            ChannelRoles {
+                moderators: HashSet::from(["mod".to_string()]),
+                vips: HashSet::new(),
+            },
+        );
+
+        let mut message = HandleMessage {
+            channel_name: "715209".to_string(),
+            sender_name: "mod".to_string(),
+            sender_id: "1".to_string(),
+            broadcaster: false,
+            moderator: false,
+            vip: false,
+            subscriber: false,
+            message: "!bbs".to_string(),
+            reward: None,
+        };
+
+        roles.apply(&mut message).await;
+        assert!(message.moderator);
+        assert!(!message.vip);
+
+        // The badges still count without the API
+        message.sender_name = "other".to_string();
+        message.moderator = false;
+        message.vip = true;
+        roles.apply(&mut message).await;
+        assert!(!message.moderator);
+        assert!(message.vip);
+    }
+}
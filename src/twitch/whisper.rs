@@ -0,0 +1,35 @@
+use serde_json::json;
+
+use super::{oauth, TwitchError};
+
+const WHISPERS_URL: &str = "https://api.twitch.tv/helix/whispers";
+
+/// Whispers a user through the Helix API, Twitch doesn't deliver whispers
+/// sent over IRC anymore.
+///
+/// The oauth needs the `user:manage:whispers` scope.
+pub async fn send(token: &str, to_user_id: &str, message: &str) -> Result<(), TwitchError> {
+    // The user id of the bot and the client id belong to the oauth
+    let info = oauth::token_info(token).await?;
+
+    let response = reqwest::Client::new()
+        .post(WHISPERS_URL)
+        .query(&[
+            ("from_user_id", &info.user_id),
+            ("to_user_id", &to_user_id.to_owned()),
+        ])
+        .bearer_auth(token)
+        .header("Client-Id", &info.client_id)
+        .json(&json!({ "message": message }))
+        .send()
+        .await?;
+
+    let status = response.status();
+
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(TwitchError::Whisper(format!("{} {}", status, body)));
+    }
+
+    Ok(())
+}
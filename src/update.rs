@@ -0,0 +1,95 @@
+//! Looks up the latest release of belabot on GitHub.
+
+use serde::Deserialize;
+use tokio::time::{Duration, Instant};
+use tracing::warn;
+
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/715209/belabot/releases/latest";
+
+/// GitHub is asked at most once per this, failed checks count as well.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The version of this build.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The git commit of this build, `unknown` when built without git.
+pub const GIT_HASH: &str = env!("BELABOT_GIT_HASH");
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+}
+
+/// The result of the last check.
+#[derive(Debug, Default)]
+pub struct UpdateCheck {
+    last: Option<(Instant, Option<String>)>,
+}
+
+impl UpdateCheck {
+    /// The latest version when it's newer than this build, `None` when this
+    /// is the latest or GitHub couldn't be reached.
+    pub async fn newer_version(&mut self) -> Option<String> {
+        if let Some((checked, newer)) = &self.last {
+            if checked.elapsed() < CHECK_INTERVAL {
+                return newer.to_owned();
+            }
+        }
+
+        let newer = match latest_release().await {
+            Ok(latest) => is_newer(&latest, VERSION).then_some(latest),
+            Err(e) => {
+                warn!(?e, "failed to check for a new version");
+                None
+            }
+        };
+
+        self.last = Some((Instant::now(), newer.to_owned()));
+
+        newer
+    }
+}
+
+async fn latest_release() -> Result<String, reqwest::Error> {
+    let release = reqwest::Client::new()
+        .get(LATEST_RELEASE_URL)
+        // GitHub rejects requests without one
+        .header(reqwest::header::USER_AGENT, format!("belabot/{}", VERSION))
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Release>()
+        .await?;
+
+    Ok(release.tag_name.trim_start_matches('v').to_owned())
+}
+
+/// Compares versions such as `0.3.0`, parts which aren't numbers count as 0.
+fn is_newer(latest: &str, current: &str) -> bool {
+    let parse = |version: &str| {
+        version
+            .split(['.', '-', '+'])
+            .take(3)
+            .map(|part| part.parse::<u64>().unwrap_or(0))
+            .collect::<Vec<_>>()
+    };
+
+    parse(latest) > parse(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn newer_versions() {
+        assert!(is_newer("0.3.1", "0.3.0"));
+        assert!(is_newer("0.10.0", "0.9.9"));
+        assert!(is_newer("1.0.0", "0.3.0"));
+        assert!(!is_newer("0.3.0", "0.3.0"));
+        assert!(!is_newer("0.2.9", "0.3.0"));
+    }
+}
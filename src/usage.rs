@@ -0,0 +1,129 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::Mutex,
+    time::{self, Duration},
+};
+use tracing::{error, warn};
+
+use crate::config::BotCommand;
+
+/// How many times commands were used.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Counts {
+    pub commands: HashMap<BotCommand, u64>,
+    pub users: HashMap<String, u64>,
+}
+
+impl Counts {
+    fn add(&mut self, command: &BotCommand, user: &str) {
+        *self.commands.entry(command.to_owned()).or_default() += 1;
+        *self.users.entry(user.to_owned()).or_default() += 1;
+    }
+}
+
+/// Command usage of this session and of all time.
+#[derive(Debug, Default)]
+pub struct Usage {
+    pub session: Counts,
+    pub total: Counts,
+    /// Whether there is usage which hasn't been saved yet
+    dirty: bool,
+}
+
+impl Usage {
+    /// Loads the total usage, starts from zero when the file can't be read.
+    pub fn load<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let total = match std::fs::read_to_string(path) {
+            Ok(file) => serde_json::from_str(&file).unwrap_or_else(|e| {
+                warn!(?e, "invalid usage stats, starting from zero");
+                Counts::default()
+            }),
+            Err(_) => Counts::default(),
+        };
+
+        Self {
+            total,
+            ..Default::default()
+        }
+    }
+
+    pub fn add(&mut self, command: &BotCommand, user: &str) {
+        self.session.add(command, user);
+        self.total.add(command, user);
+        self.dirty = true;
+    }
+
+    /// Writes the total usage to the file when something changed.
+    pub fn save(&mut self, path: &Path) -> std::io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let contents = serde_json::to_string_pretty(&self.total)?;
+        crate::config::write_atomic(path, contents.as_bytes())?;
+
+        self.dirty = false;
+
+        Ok(())
+    }
+}
+
+/// The most used entries, most used first.
+pub fn top<K>(counts: &HashMap<K, u64>, amount: usize) -> Vec<(&K, u64)>
+where
+    K: Ord,
+{
+    let mut top = counts.iter().map(|(k, v)| (k, *v)).collect::<Vec<_>>();
+
+    // Sort by key as well so entries with the same count don't move around
+    top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    top.truncate(amount);
+
+    top
+}
+
+/// Saves the usage to the file every interval.
+pub async fn save_loop(usage: Arc<Mutex<Usage>>, path: PathBuf, interval: Duration) {
+    let mut interval = time::interval(interval);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = usage.lock().await.save(&path) {
+            error!(?e, "failed to save the usage stats");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn top_commands() {
+        let mut usage = Usage::default();
+        usage.add(&BotCommand::Stats, "b3ck");
+        usage.add(&BotCommand::Stats, "715209");
+        usage.add(&BotCommand::Bitrate, "715209");
+
+        let commands = top(&usage.session.commands, 5);
+        assert_eq!(
+            commands,
+            vec![(&BotCommand::Stats, 2), (&BotCommand::Bitrate, 1)]
+        );
+
+        let users = top(&usage.total.users, 1);
+        assert_eq!(users, vec![(&"715209".to_string(), 2)]);
+    }
+}